@@ -1,6 +1,6 @@
-use queue::{consumer::messages::{ConsumerMsg, TransferMsg}, producer::messages::{ProducerMsg, ResultMsg}};
+use queue::{consumer::messages::{ConsumerMsg, TransferMsg}, producer::messages::{ErrorMsg, ProducerMsg, ResultMsg, SignatureMsg}};
 
-use crate::domain::{payment::{IncomingPayment, ProcessedPayment}, pubkey::Pubkey, transfer::TransferDataParsed};
+use crate::domain::{payment::{unix_now, IncomingPayment, PaymentError, ProcessedPayment}, pubkey::Pubkey, transfer::TransferDataParsed};
 
 pub trait IntoIncomingPayment {
     fn into_domain(self, pubkey: Pubkey) -> IncomingPayment;
@@ -9,17 +9,38 @@ pub trait IntoIncomingPayment {
 impl IntoIncomingPayment for ConsumerMsg<TransferMsg> {
     fn into_domain(self, pubkey: Pubkey) -> IncomingPayment {
         let msg = self.msg;
-        IncomingPayment::new(msg.id, self.tag, TransferDataParsed::new(pubkey, msg.amount))
+
+        // A ttl counts from the moment the payment is received
+        let expires_at = msg.expires_at.or(msg.ttl.map(|ttl| unix_now().saturating_add(ttl)));
+
+        IncomingPayment::new(msg.id, self.tag, TransferDataParsed::new(pubkey, msg.amount, msg.mint))
+            .with_memo(msg.memo)
+            .with_references(msg.references)
+            .with_expires_at(expires_at)
     }
 }
 
 impl Into<ProducerMsg<ResultMsg>> for ProcessedPayment {
     fn into(mut self) -> ProducerMsg<ResultMsg> {
-        let signatures = self.take_signatures();
+        let signatures = self.take_signatures().map(|transfers| transfers
+            .into_iter()
+            .map(|t| {
+                let (signature, from, amount, height, block_time) = t.expose();
+                SignatureMsg::new(signature, from, amount, height, block_time)
+            })
+            .collect());
+
         let tag = self.tag();
-        let error = self.take_error();
+        let error = self.take_error().map(|e| match e {
+            PaymentError::ConflictingAmount => ErrorMsg::ConflictingAmount,
+            PaymentError::Expired => ErrorMsg::Expired,
+            PaymentError::DuplicateReference => ErrorMsg::DuplicateReference,
+        });
+        let completed_at = self.completed_at();
+        let received = self.received();
 
-        let msg = ResultMsg::new(self.id(), signatures, error);
+        let msg = ResultMsg::new(self.id(), signatures, error, completed_at)
+            .with_received(received);
         ProducerMsg::new(msg, tag)
     }
 }
\ No newline at end of file
@@ -1,11 +1,11 @@
-use anyhow::Context;
+use anyhow::{bail, Context};
 use const_format::concatcp;
 use log::error;
 use queue::{consumer::messages::{ConsumerMsg, TransferMsg}, producer::messages::{ProducerMsg, ResultMsg}};
 use tokio::{select, sync::mpsc::{UnboundedReceiver, UnboundedSender}, task};
 use tokio_util::sync::CancellationToken;
 
-use crate::{application::mappers::IntoIncomingPayment, domain::{payment::{IncomingPayment, ProcessedPaymentMeta}, pubkey::Pubkey, state::State}};
+use crate::{application::mappers::IntoIncomingPayment, domain::{payment::{IncomingPayment, ProcessedPayment}, pubkey::Pubkey}};
 
 pub trait ParserPort {
     fn to_pubkey(&self, address: &String) -> anyhow::Result<Pubkey>;
@@ -14,22 +14,27 @@ pub trait ParserPort {
 pub struct TransferActor<P> {
     messages_rx: UnboundedReceiver<ConsumerMsg<TransferMsg>>,
     payments_tx: UnboundedSender<IncomingPayment>,
-    state_tx: UnboundedSender<State>,
-    processed_rx: UnboundedReceiver<ProcessedPaymentMeta>,
+    processed_rx: UnboundedReceiver<ProcessedPayment>,
     producer_tx: UnboundedSender<ProducerMsg<ResultMsg>>,
     parser: P,
+    memos: bool,
 }
 
 impl<P: ParserPort> TransferActor<P> {
     pub fn new(
         messages_rx: UnboundedReceiver<ConsumerMsg<TransferMsg>>,
         payments_tx: UnboundedSender<IncomingPayment>,
-        state_tx: UnboundedSender<State>,
-        processed_rx: UnboundedReceiver<ProcessedPaymentMeta>,
+        processed_rx: UnboundedReceiver<ProcessedPayment>,
         producer_tx: UnboundedSender<ProducerMsg<ResultMsg>>,
         parser: P
     ) -> Self {
-        Self { messages_rx, payments_tx, state_tx, processed_rx, producer_tx, parser }
+        Self { messages_rx, payments_tx, processed_rx, producer_tx, parser, memos: false }
+    }
+
+    /// Accepts payments with a memo, only if the detected transfers carry the memos of their transactions
+    pub fn with_memos(mut self, memos: bool) -> Self {
+        self.memos = memos;
+        self
     }
 
     pub async fn start(mut self, token: CancellationToken) {
@@ -41,7 +46,7 @@ impl<P: ParserPort> TransferActor<P> {
                     error!("err process_message() in {}: {:#?}", FN_CTX, e);
                 },
 
-                Some(meta) = self.processed_rx.recv() => if let Err(e) = self.process_payment(meta) {
+                Some(payment) = self.processed_rx.recv() => if let Err(e) = self.process_payment(payment) {
                     error!("err process_result() in {}: {:#?}", FN_CTX, e);
                 },
 
@@ -58,26 +63,30 @@ impl<P: ParserPort> TransferActor<P> {
         let pubkey = self.parser.to_pubkey(&consumer_msg.msg.address)
             .context(concatcp!("err parser.to_pubkey() in ", FN_CTX))?;
 
+        if let Some(mint) = &consumer_msg.msg.mint {
+            self.parser.to_pubkey(mint)
+                .context(concatcp!("err parser.to_pubkey(mint) in ", FN_CTX))?;
+        }
+
+        // A memo payment would never be matched
+        if consumer_msg.msg.memo.is_some() && !self.memos {
+            bail!("err 'memo matching is disabled' in {}, payment: {}", FN_CTX, consumer_msg.msg.id);
+        }
+
+        for reference in &consumer_msg.msg.references {
+            self.parser.to_pubkey(reference)
+                .context(concatcp!("err parser.to_pubkey(reference) in ", FN_CTX))?;
+        }
+
         self.payments_tx.send(consumer_msg.into_domain(pubkey))
             .context(concatcp!("err payments_tx.send() in ", FN_CTX))?;
 
-        self.state_tx.send(State::Running)
-            .context(concatcp!("err state_tx.send() in ", FN_CTX))?;
-
         Ok(())
     }
 
-    fn process_payment(&self, meta: ProcessedPaymentMeta) -> anyhow::Result<()> {
-        const FN_CTX: &str = "process_payment()";
-
-        let last = meta.last();
-        if last {
-            self.state_tx.send(State::Stopping)
-                .context(concatcp!("err state_tx.send() in ", FN_CTX))?;
-        }
-
-        self.producer_tx.send(meta.payment().into())
-            .context(concatcp!("err producer_tx.send() in ", FN_CTX))?;
+    fn process_payment(&self, payment: ProcessedPayment) -> anyhow::Result<()> {
+        self.producer_tx.send(payment.into())
+            .context("err producer_tx.send() in process_payment()")?;
 
         Ok(())
     }
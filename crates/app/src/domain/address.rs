@@ -0,0 +1,11 @@
+use tokio::sync::mpsc::UnboundedSender;
+
+use super::pubkey::Pubkey;
+
+pub type AddressTx = UnboundedSender<AddressEvent>;
+
+/// Tells detection strategies watching single addresses which ones have pending payments
+pub enum AddressEvent {
+    Pending(Pubkey),
+    Completed(Pubkey),
+}
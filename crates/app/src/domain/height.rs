@@ -1,4 +1,15 @@
 use tokio::sync::mpsc::UnboundedSender;
 
 pub type Height = u64;
-pub type HeightTx = UnboundedSender<Height>;
\ No newline at end of file
+pub type HeightTx = UnboundedSender<HeightMsg>;
+
+pub enum HeightMsg {
+    /// Every block up to the height is either processed or queued for retry
+    Processed(Height),
+
+    /// The block failed to process and is queued for retry
+    Failed(Height),
+
+    /// The previously failed block is processed
+    Retried(Height),
+}
@@ -1,5 +1,6 @@
 pub mod payment;
 pub mod pubkey;
 pub mod transfer;
-pub mod state;
-pub mod height;
\ No newline at end of file
+pub mod pending;
+pub mod address;
+pub mod height;
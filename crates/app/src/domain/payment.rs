@@ -1,16 +1,52 @@
-use std::mem;
+use std::{mem, time::{SystemTime, UNIX_EPOCH}};
 
-use super::transfer::TransferDataParsed;
+use super::{height::Height, transfer::TransferDataParsed};
 
 pub struct IncomingPayment {
     id: String,
     tag: u64,
     transfer_data: TransferDataParsed,
+    memo: Option<String>,
+    references: Vec<String>,
+    expires_at: Option<u64>,
 }
 
 impl IncomingPayment {
     pub fn new(id: String, tag: u64, transfer_data: TransferDataParsed) -> Self {
-        Self { id, tag, transfer_data }
+        Self { id, tag, transfer_data, memo: None, references: Vec::new(), expires_at: None }
+    }
+
+    /// Sets the unix timestamp the payment expires at, `None` if it stays open until it is paid
+    pub fn with_expires_at(mut self, expires_at: Option<u64>) -> Self {
+        self.expires_at = expires_at;
+        self
+    }
+
+    #[inline]
+    pub fn expires_at(&self) -> Option<u64> {
+        self.expires_at
+    }
+
+    /// Only transfers whose transaction holds one of the reference keys are credited to the payment
+    pub fn with_references(mut self, references: Vec<String>) -> Self {
+        self.references = references;
+        self
+    }
+
+    #[inline]
+    pub fn take_references(&mut self) -> Vec<String> {
+        mem::take(&mut self.references)
+    }
+
+    /// Only transfers carrying the memo are credited to the payment
+    pub fn with_memo(mut self, memo: Option<String>) -> Self {
+        self.memo = memo;
+        self
+    }
+
+    #[inline]
+    pub fn take_memo(&mut self) -> Option<String> {
+        mem::take(&mut self.memo)
     }
 
     #[inline]
@@ -24,42 +60,73 @@ impl IncomingPayment {
     }
 }
 
-pub struct ProcessedPaymentMeta {
-    payment: ProcessedPayment,
-    last: bool,
+/// A transfer credited to a payment
+pub struct PaymentTransfer {
+    signature: String,
+    from: Option<String>,
+    amount: u64,
+    height: Height,
+    block_time: Option<i64>,
 }
 
-impl ProcessedPaymentMeta {
-    pub fn new(payment: ProcessedPayment, last: bool) -> Self {
-        Self { payment, last }
+impl PaymentTransfer {
+    pub fn new(signature: String, from: Option<String>, amount: u64, height: Height, block_time: Option<i64>) -> Self {
+        Self { signature, from, amount, height, block_time }
     }
 
     #[inline]
-    pub fn payment(self) -> ProcessedPayment {
-        self.payment
+    pub fn expose(self) -> (String, Option<String>, u64, Height, Option<i64>) {
+        (self.signature, self.from, self.amount, self.height, self.block_time)
     }
+}
 
-    #[inline]
-    pub fn last(&self) -> bool {
-        self.last
-    }
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaymentError {
+    /// Another open payment on the address could be paid by the same amount
+    ConflictingAmount,
+
+    /// The payment was not paid in full before it expired
+    Expired,
+
+    /// One of the references belongs to another open payment
+    DuplicateReference,
 }
 
 pub struct ProcessedPayment {
     id: String,
     tag: u64,
-    signatures: Option<Vec<String>>,
-    error: Option<()>,
+    signatures: Option<Vec<PaymentTransfer>>,
+    error: Option<PaymentError>,
+    completed_at: u64,
+    received: Option<u64>,
 }
 
 impl ProcessedPayment {
+    /// `completed_at` is the unix timestamp the payment was completed at
     pub fn new(
         id: String,
         tag: u64,
-        signatures: Option<Vec<String>>,
-        error: Option<()>,
+        signatures: Option<Vec<PaymentTransfer>>,
+        error: Option<PaymentError>,
+        completed_at: u64,
     ) -> Self {
-        Self { id, tag, signatures, error }
+        Self { id, tag, signatures, error, completed_at, received: None }
+    }
+
+    /// Sets the amount credited to a payment which was closed before it was paid in full
+    pub fn with_received(mut self, received: Option<u64>) -> Self {
+        self.received = received;
+        self
+    }
+
+    #[inline]
+    pub fn received(&self) -> Option<u64> {
+        self.received
+    }
+
+    #[inline]
+    pub fn completed_at(&self) -> u64 {
+        self.completed_at
     }
 
     #[inline]
@@ -73,12 +140,21 @@ impl ProcessedPayment {
     }
 
     #[inline]
-    pub fn take_signatures(&mut self) -> Option<Vec<String>> {
+    pub fn take_signatures(&mut self) -> Option<Vec<PaymentTransfer>> {
         mem::take(&mut self.signatures)
     }
 
     #[inline]
-    pub fn take_error(&mut self) -> Option<()> {
+    pub fn take_error(&mut self) -> Option<PaymentError> {
         mem::take(&mut self.error)
     }
+}
+
+/// Seconds since the unix epoch, payments expire and complete at unix timestamps
+#[inline]
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
 }
\ No newline at end of file
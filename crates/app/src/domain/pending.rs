@@ -0,0 +1,6 @@
+use tokio::sync::watch;
+
+/// Number of payments waiting for transfers, owned by the payments layer.
+/// Slots are only subscribed to while it is above zero.
+pub type PendingTx = watch::Sender<usize>;
+pub type PendingRx = watch::Receiver<usize>;
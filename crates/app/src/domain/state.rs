@@ -1,4 +0,0 @@
-pub enum State {
-    Running,
-    Stopping,
-}
\ No newline at end of file
@@ -1,14 +1,38 @@
-use super::pubkey::Pubkey;
+use std::mem;
+
+use tokio::sync::mpsc::UnboundedSender;
+
+use super::{height::Height, pubkey::Pubkey};
+
+pub type TransferTx = UnboundedSender<TransferEvent>;
 
 #[derive(Debug)]
 pub struct TransferData {
     address: String,
     amount: u64,
+    mint: Option<String>,
+    source: Option<String>,
 }
 
 impl TransferData {
-    pub fn new(address: String, amount: u64) -> Self {
-        Self { address, amount }
+    pub fn new(address: String, amount: u64, mint: Option<String>) -> Self {
+        Self { address, amount, mint, source: None }
+    }
+
+    /// Sets the account which funded the transfer
+    pub fn with_source(mut self, source: Option<String>) -> Self {
+        self.source = source;
+        self
+    }
+
+    #[inline]
+    pub fn take_source(&mut self) -> Option<String> {
+        mem::take(&mut self.source)
+    }
+
+    #[inline]
+    pub fn take_mint(&mut self) -> Option<String> {
+        mem::take(&mut self.mint)
     }
 
     #[inline]
@@ -26,11 +50,28 @@ impl TransferData {
 pub struct TransferDataParsed {
     pubkey: Pubkey,
     amount: u64,
+    mint: Option<String>,
+    source: Option<String>,
 }
 
 impl TransferDataParsed {
-    pub fn new(pubkey: Pubkey, amount: u64) -> Self {
-        Self { pubkey, amount }
+    pub fn new(pubkey: Pubkey, amount: u64, mint: Option<String>) -> Self {
+        Self { pubkey, amount, mint, source: None }
+    }
+
+    pub fn with_source(mut self, source: Option<String>) -> Self {
+        self.source = source;
+        self
+    }
+
+    #[inline]
+    pub fn take_source(&mut self) -> Option<String> {
+        mem::take(&mut self.source)
+    }
+
+    #[inline]
+    pub fn take_mint(&mut self) -> Option<String> {
+        mem::take(&mut self.mint)
     }
 
     #[inline]
@@ -48,11 +89,47 @@ impl TransferDataParsed {
 pub struct IncomingTransfer {
     transfer_data: TransferData,
     signatures: Vec<String>,
+    block_time: Option<i64>,
+    memo: Option<String>,
+    account_keys: Vec<String>,
 }
 
 impl IncomingTransfer {
     pub fn new(transfer_data: TransferData, signatures: Vec<String>) -> Self {
-        Self { transfer_data, signatures }
+        Self { transfer_data, signatures, block_time: None, memo: None, account_keys: Vec::new() }
+    }
+
+    /// Sets every account key of the transaction, references of payments are looked up in them
+    pub fn with_account_keys(mut self, account_keys: Vec<String>) -> Self {
+        self.account_keys = account_keys;
+        self
+    }
+
+    #[inline]
+    pub fn take_account_keys(&mut self) -> Vec<String> {
+        mem::take(&mut self.account_keys)
+    }
+
+    /// Sets the memo of the transaction holding the transfer
+    pub fn with_memo(mut self, memo: Option<String>) -> Self {
+        self.memo = memo;
+        self
+    }
+
+    #[inline]
+    pub fn take_memo(&mut self) -> Option<String> {
+        mem::take(&mut self.memo)
+    }
+
+    /// Sets the unix timestamp of the block, `None` if the node does not know it
+    pub fn with_block_time(mut self, block_time: Option<i64>) -> Self {
+        self.block_time = block_time;
+        self
+    }
+
+    #[inline]
+    pub fn block_time(&self) -> Option<i64> {
+        self.block_time
     }
 
     #[inline]
@@ -65,15 +142,65 @@ impl IncomingTransfer {
 pub struct IncomingTransferParsed {
     transfer_data: TransferDataParsed,
     signatures: Vec<String>,
+    height: Height,
+    block_time: Option<i64>,
+    memo: Option<String>,
+    account_keys: Vec<String>,
 }
 
 impl IncomingTransferParsed {
-    pub fn new(transfer_data: TransferDataParsed, signatures: Vec<String>) -> Self {
-        Self { transfer_data, signatures }
+    pub fn new(transfer_data: TransferDataParsed, signatures: Vec<String>, height: Height) -> Self {
+        Self { transfer_data, signatures, height, block_time: None, memo: None, account_keys: Vec::new() }
+    }
+
+    pub fn with_account_keys(mut self, account_keys: Vec<String>) -> Self {
+        self.account_keys = account_keys;
+        self
+    }
+
+    #[inline]
+    pub fn take_account_keys(&mut self) -> Vec<String> {
+        mem::take(&mut self.account_keys)
+    }
+
+    pub fn with_memo(mut self, memo: Option<String>) -> Self {
+        self.memo = memo;
+        self
+    }
+
+    #[inline]
+    pub fn take_memo(&mut self) -> Option<String> {
+        mem::take(&mut self.memo)
+    }
+
+    pub fn with_block_time(mut self, block_time: Option<i64>) -> Self {
+        self.block_time = block_time;
+        self
+    }
+
+    #[inline]
+    pub fn height(&self) -> Height {
+        self.height
+    }
+
+    #[inline]
+    pub fn block_time(&self) -> Option<i64> {
+        self.block_time
     }
 
     #[inline]
     pub fn expose(self) -> (TransferDataParsed, Vec<String>) {
         (self.transfer_data, self.signatures)
     }
-}
\ No newline at end of file
+}
+
+pub enum TransferEvent {
+    /// The transfer is credited, it can be reverted until its block is finalized
+    Incoming(IncomingTransferParsed),
+
+    /// Blocks up to the height are finalized, so their credits are final
+    Finalized(Height),
+
+    /// The block dropped off the canonical chain, so its credits are reverted
+    Reverted(Height),
+}
@@ -1,3 +1,4 @@
 pub mod args;
 pub mod rabbitmq;
-pub mod network;
\ No newline at end of file
+pub mod network;
+pub mod metrics;
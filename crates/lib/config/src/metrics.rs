@@ -0,0 +1,11 @@
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub struct MetricsConfig {
+    /// Socket address of the Prometheus endpoint, metrics are not exported if unset
+    pub address: Option<String>,
+}
+
+pub fn load() -> anyhow::Result<MetricsConfig> {
+    Ok(envy::prefixed("METRICS_").from_env()?)
+}
@@ -7,6 +7,12 @@ pub struct NetworkConfig {
     pub queues: QueuesConfig,
     pub rpc: RpcConfig,
     pub db: DbConfig,
+
+    #[serde(default)]
+    pub block: BlockConfig,
+
+    #[serde(default)]
+    pub payments: PaymentsConfig,
 }
 
 #[derive(Deserialize)]
@@ -17,8 +23,25 @@ pub struct QueuesConfig {
 
 #[derive(Deserialize)]
 pub struct RpcConfig {
+    pub endpoints: Vec<EndpointConfig>,
+}
+
+#[derive(Deserialize)]
+pub struct EndpointConfig {
     pub http_endpoint_url: String,
     pub ws_endpoint_url: String,
+
+    /// Endpoints with a lower value are preferred, equal ones are ranked by their health
+    #[serde(default)]
+    pub priority: u32,
+
+    /// Client-side limit of requests per second, unlimited if not set
+    #[serde(default)]
+    pub requests_per_second: Option<u32>,
+
+    /// Number of requests which may be sent at once, defaults to `requests_per_second`
+    #[serde(default)]
+    pub burst: Option<u32>,
 }
 
 #[derive(Deserialize)]
@@ -27,6 +50,177 @@ pub struct DbConfig {
     pub height_path: String,
 }
 
+#[derive(Deserialize)]
+pub struct PaymentsConfig {
+    #[serde(default)]
+    pub matching: Matching,
+
+    /// Amount a transfer may pay above the expected one with `unique` matching, in base units.
+    /// Underpayments are never credited.
+    #[serde(default)]
+    pub amount_tolerance: u64,
+
+    /// How often payments are checked for expiry
+    #[serde(default="default_sweep_interval_ms")]
+    pub sweep_interval_ms: u64,
+
+    /// Accepts payments matched by memo, block detection must read instructions with
+    /// `full` transaction details or `instructions` transfer parsing
+    #[serde(default)]
+    pub memos: bool,
+}
+
+/// Decides which open payment of an address a transfer is credited to
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Matching {
+    /// The oldest payment
+    #[default]
+    Fifo,
+
+    /// The payment whose remaining amount equals the transfer, the oldest one if there is none
+    Amount,
+
+    /// Every payment on an address has its own amount, a payment conflicting with another one is refused
+    Unique,
+}
+
+#[derive(Deserialize)]
+pub struct BlockConfig {
+    #[serde(default="default_skip_failed_transactions")]
+    pub skip_failed_transactions: bool,
+
+    /// Number of requests sent at once
+    #[serde(default="default_concurrency")]
+    pub concurrency: usize,
+
+    /// Number of `getBlock` calls sent in a single JSON-RPC batch
+    #[serde(default="default_batch_size")]
+    pub batch_size: usize,
+
+    #[serde(default)]
+    pub commitment: Commitment,
+
+    /// Number of slots the processed range trails the notified slot by
+    #[serde(default="default_confirmation_lag")]
+    pub confirmation_lag: u64,
+
+    #[serde(default)]
+    pub detection: Detection,
+
+    #[serde(default)]
+    pub transaction_details: TransactionDetails,
+
+    #[serde(default)]
+    pub transfer_parsing: TransferParsing,
+
+    /// Interval between address lookups with `signatures` detection
+    #[serde(default="default_poll_interval_ms")]
+    pub poll_interval_ms: u64,
+}
+
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Commitment {
+    /// Transfers are credited faster, but reverted if their block drops off the chain
+    Confirmed,
+
+    #[default]
+    Finalized,
+}
+
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum TransactionDetails {
+    Full,
+
+    /// Only account keys and balances are downloaded, which is all balance diffs need
+    #[default]
+    Accounts,
+}
+
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum TransferParsing {
+    /// SOL credits are balance diffs
+    #[default]
+    Balances,
+
+    /// SOL credits are System Program transfers, whole `jsonParsed` transactions are downloaded
+    Instructions,
+}
+
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Detection {
+    /// Every block is fetched and scanned
+    #[default]
+    Blocks,
+
+    /// Only addresses of pending payments are watched with `accountSubscribe`, requires `finalized` commitment
+    Accounts,
+
+    /// Addresses of pending payments are polled with `getSignaturesForAddress`, requires `finalized` commitment
+    Signatures,
+}
+
+impl Default for PaymentsConfig {
+    fn default() -> Self {
+        Self {
+            matching: Default::default(),
+            amount_tolerance: Default::default(),
+            sweep_interval_ms: default_sweep_interval_ms(),
+            memos: Default::default(),
+        }
+    }
+}
+
+impl Default for BlockConfig {
+    fn default() -> Self {
+        Self {
+            skip_failed_transactions: default_skip_failed_transactions(),
+            concurrency: default_concurrency(),
+            batch_size: default_batch_size(),
+            commitment: Default::default(),
+            confirmation_lag: default_confirmation_lag(),
+            detection: Default::default(),
+            transaction_details: Default::default(),
+            transfer_parsing: Default::default(),
+            poll_interval_ms: default_poll_interval_ms(),
+        }
+    }
+}
+
+#[inline]
+fn default_skip_failed_transactions() -> bool {
+    true
+}
+
+#[inline]
+fn default_concurrency() -> usize {
+    1
+}
+
+#[inline]
+fn default_batch_size() -> usize {
+    1
+}
+
+#[inline]
+fn default_confirmation_lag() -> u64 {
+    40
+}
+
+#[inline]
+fn default_poll_interval_ms() -> u64 {
+    2000
+}
+
+#[inline]
+fn default_sweep_interval_ms() -> u64 {
+    1000
+}
+
 pub fn load(path: &str) -> anyhow::Result<NetworkConfig> {
     let content = fs::read_to_string(path)?;
     Ok(toml::from_str(&content)?)
@@ -0,0 +1,19 @@
+use std::net::SocketAddr;
+
+use config::metrics::MetricsConfig;
+use metrics_exporter_prometheus::PrometheusBuilder;
+
+/// Serves collected metrics on `/metrics` for Prometheus, does nothing if no address is configured
+pub fn install(config: &MetricsConfig) -> anyhow::Result<()> {
+    let Some(address) = &config.address else {
+        return Ok(());
+    };
+
+    let address: SocketAddr = address.parse()?;
+
+    PrometheusBuilder::new()
+        .with_http_listener(address)
+        .install()?;
+
+    Ok(())
+}
@@ -0,0 +1,181 @@
+//! Compares the borrowed `GetBlockRes` with a mapping into owned strings, as it was done before.
+//!
+//! Recorded mainnet blocks are read from `benches/blocks/*.json` and `benches/blocks/*.json.gz`,
+//! they are committed gzipped and saved with `benches/record_block.sh SLOT [RPC]`.
+//! The bench refuses to run without a recorded block.
+//!
+//! Run with `cargo bench -p solana --bench get_block`.
+
+use std::{alloc::{GlobalAlloc, Layout, System}, collections::HashSet, fs, hint::black_box, io::Read, path::Path, sync::atomic::{AtomicUsize, Ordering}, time::{Duration, Instant}};
+
+use app::domain::transfer::{IncomingTransfer, TransferData};
+use flate2::read::GzDecoder;
+use itertools::izip;
+use serde::Deserialize;
+use solana::data::block::res::GetBlockRes;
+
+const BLOCKS_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/benches/blocks");
+const ITERATIONS: u32 = 20;
+
+const PENDING_ADDRESS: &str = "7Np41oeYqPefeNQEHSv1UDhYrehxin3NStELsSKCT4K2";
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+struct CountingAlloc;
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+#[derive(Deserialize)]
+struct OwnedBlock {
+    transactions: Vec<OwnedTransactionRes>,
+}
+
+#[derive(Deserialize)]
+struct OwnedTransactionRes {
+    meta: OwnedMeta,
+    transaction: OwnedTransaction,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OwnedMeta {
+    post_balances: Vec<u64>,
+    pre_balances: Vec<u64>,
+
+    #[serde(default)]
+    loaded_addresses: Option<OwnedLoadedAddresses>,
+}
+
+#[derive(Deserialize, Default)]
+struct OwnedLoadedAddresses {
+    writable: Vec<String>,
+    readonly: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct OwnedTransaction {
+    message: OwnedMessage,
+    signatures: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OwnedMessage {
+    account_keys: Vec<String>,
+}
+
+fn owned_transfers(b: &[u8]) -> Vec<IncomingTransfer> {
+    let block: OwnedBlock = sonic_rs::from_slice(b).expect("valid block");
+
+    block.transactions
+        .into_iter()
+        .flat_map(|tx| {
+            let loaded = tx.meta.loaded_addresses.unwrap_or_default();
+            let account_keys = tx.transaction.message.account_keys
+                .into_iter()
+                .chain(loaded.writable)
+                .chain(loaded.readonly);
+
+            let signatures = tx.transaction.signatures;
+
+            izip!(account_keys, tx.meta.pre_balances, tx.meta.post_balances)
+                .filter(|(_, pre, post)| pre < post)
+                .map(|(addr, pre, post)| IncomingTransfer::new(TransferData::new(addr, post - pre, None), signatures.clone()))
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+fn borrowed_transfers(b: &[u8], pending: Option<&HashSet<String>>) -> Vec<IncomingTransfer> {
+    let block: GetBlockRes = sonic_rs::from_slice(b).expect("valid block");
+
+    match pending {
+        Some(pending) => block.into_transfers(true, |a| pending.contains(a)),
+        None => block.into_transfers(true, |_| true),
+    }
+}
+
+fn read_block(path: &Path) -> Option<(String, Vec<u8>)> {
+    let name = path.file_name()?.to_string_lossy().into_owned();
+    let b = fs::read(path).ok()?;
+
+    if name.ends_with(".json") {
+        return Some((name, b));
+    }
+
+    if !name.ends_with(".json.gz") {
+        return None;
+    }
+
+    let mut decoded = Vec::with_capacity(b.len() * 4);
+    GzDecoder::new(&b[..]).read_to_end(&mut decoded).expect("valid gzip");
+
+    Some((name, decoded))
+}
+
+fn load_blocks() -> Vec<(String, Vec<u8>)> {
+    let mut recorded: Vec<_> = fs::read_dir(Path::new(BLOCKS_DIR))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|e| read_block(&e.path()))
+        .collect();
+
+    assert!(!recorded.is_empty(), "no recorded blocks in {}, record one with benches/record_block.sh SLOT", BLOCKS_DIR);
+
+    recorded.sort_by(|(a, _), (b, _)| a.cmp(b));
+    recorded
+}
+
+fn measure<F: Fn() -> Vec<IncomingTransfer>>(f: F) -> (Duration, usize, usize) {
+    // Warm up caches and the allocator
+    let transfers = black_box(f()).len();
+
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let started_at = Instant::now();
+
+    for _ in 0..ITERATIONS {
+        black_box(f());
+    }
+
+    let elapsed = started_at.elapsed() / ITERATIONS;
+    let allocations = (ALLOCATIONS.load(Ordering::Relaxed) - allocations) / ITERATIONS as usize;
+
+    (elapsed, allocations, transfers)
+}
+
+fn main() {
+    let pending = HashSet::from([PENDING_ADDRESS.to_string()]);
+
+    for (name, b) in load_blocks() {
+        println!("{} - {:.2} MiB", name, b.len() as f64 / (1024.0 * 1024.0));
+
+        let cases: [(&str, &dyn Fn() -> Vec<IncomingTransfer>); 3] = [
+            ("owned", &|| owned_transfers(&b)),
+            ("borrowed", &|| borrowed_transfers(&b, None)),
+            ("borrowed, pending filter", &|| borrowed_transfers(&b, Some(&pending))),
+        ];
+
+        for (case, f) in cases {
+            let (elapsed, allocations, transfers) = measure(f);
+            println!("  {:<26} {:>10.2?} {:>10} allocations {:>8} transfers", case, elapsed, allocations, transfers);
+        }
+    }
+}
@@ -0,0 +1,14 @@
+#!/bin/sh
+# Records a mainnet block for the get_block bench as benches/blocks/SLOT.json.gz
+set -eu
+
+SLOT="$1"
+RPC="${2:-https://api.mainnet-beta.solana.com}"
+DIR="$(dirname "$0")/blocks"
+
+mkdir -p "$DIR"
+
+curl -sS --compressed "$RPC" -X POST -H 'Content-Type: application/json' \
+    -d "{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"getBlock\",\"params\":[$SLOT,{\"encoding\":\"json\",\"maxSupportedTransactionVersion\":0,\"rewards\":false}]}" \
+    | jq -e .result \
+    | gzip -9 > "$DIR/$SLOT.json.gz"
@@ -0,0 +1,147 @@
+use std::future::Future;
+
+use anyhow::{bail, Context};
+use app::domain::transfer::IncomingTransfer;
+use const_format::concatcp;
+use log::error;
+
+use crate::{data::{block::{BlockRepo, BlockService}, req::{Commitment, RpcReq}}, domain::slot::Slot};
+
+use super::{res::SignatureInfo, watch::AddressWatch};
+
+/// Detects transfers by looking up signatures of watched addresses instead of scanning every block.
+/// `get_blocks` only returns slots holding transfers for the watched addresses, and `get_block`
+/// serves them from the buffer without another request.
+pub struct AddressRepo {
+    block_service: BlockService,
+    watch: AddressWatch,
+}
+
+impl AddressRepo {
+    pub fn new(block_service: BlockService, watch: AddressWatch) -> Self {
+        Self { block_service, watch }
+    }
+
+    /// Looks up every changed address, an address which failed is looked up again on the next call
+    async fn sync(&self, start_slot: Slot, commitment: Commitment) -> anyhow::Result<()> {
+        const FN_CTX: &str = "sync()";
+
+        let mut failed = 0;
+
+        for (address, until) in self.watch.take_changed() {
+            if let Err(e) = self.sync_address(&address, until, start_slot, commitment).await {
+                error!("err self.sync_address() in {}, address: {}: {:#?}", FN_CTX, address, e);
+
+                self.watch.mark_changed(&address);
+                failed += 1;
+            }
+        }
+
+        // The range must not be committed while a lookup is missing
+        if failed > 0 {
+            bail!("err 'lookup failed for {} addresses' in {}", failed, FN_CTX);
+        }
+
+        Ok(())
+    }
+
+    async fn sync_address(
+        &self,
+        address: &str,
+        until: Option<String>,
+        start_slot: Slot,
+        commitment: Commitment,
+    ) -> anyhow::Result<()> {
+        const FN_CTX: &str = "sync_address()";
+
+        let signatures = fetch_signatures(start_slot, |before| {
+            let req = RpcReq::new_get_signatures_for_address(address.to_string(), before, until.clone(), commitment);
+            async move { self.block_service.call(&req).await }
+        })
+        .await
+        .context(concatcp!("err fetch_signatures() in ", FN_CTX))?;
+
+        let Some(newest) = signatures.first().map(|s| s.signature.clone()) else {
+            return Ok(());
+        };
+
+        let skip_failed = self.block_service.skip_failed_transactions();
+        let mut found = Vec::new();
+
+        // Signatures come newest first, slots the sequencer has moved past are already processed
+        for info in signatures.into_iter().rev() {
+            if info.slot < start_slot || (skip_failed && info.err.is_some()) {
+                continue;
+            }
+
+            // The transaction may credit other addresses, they are looked up on their own
+            let transfers = self.block_service.get_transaction(info.signature, commitment, |a| a == address)
+                .await
+                .context(concatcp!("err block_service.get_transaction() in ", FN_CTX))?;
+
+            found.push((info.slot, transfers));
+        }
+
+        // Buffered only once every lookup succeeded, so a retry does not add them twice
+        for (slot, transfers) in found {
+            self.watch.push_transfers(slot, transfers);
+        }
+
+        self.watch.set_last_signature(address, newest);
+
+        Ok(())
+    }
+}
+
+/// Pages through the signatures of an address, newest first. `fetch_page` is called with the oldest
+/// signature so far, paging stops at an empty page, which `until` bounds, or below `start_slot`.
+pub async fn fetch_signatures<F, Fut>(start_slot: Slot, mut fetch_page: F) -> anyhow::Result<Vec<SignatureInfo>>
+where
+    F: FnMut(Option<String>) -> Fut,
+    Fut: Future<Output = anyhow::Result<Vec<SignatureInfo>>>,
+{
+    let mut signatures: Vec<SignatureInfo> = Vec::new();
+
+    loop {
+        let before = signatures.last().map(|s| s.signature.clone());
+        let page = fetch_page(before)
+            .await
+            .context("err fetch_page() in fetch_signatures()")?;
+
+        // Older signatures are already processed
+        let done = page.last().is_none_or(|s| s.slot < start_slot);
+        signatures.extend(page);
+
+        if done {
+            return Ok(signatures);
+        }
+    }
+}
+
+impl BlockRepo for AddressRepo {
+    async fn get_block(&self, slot: Slot, _commitment: Commitment) -> anyhow::Result<Vec<IncomingTransfer>> {
+        Ok(self.watch.take_transfers(slot))
+    }
+
+    async fn get_block_batch(&self, slots: &[Slot], _commitment: Commitment) -> anyhow::Result<Vec<anyhow::Result<Vec<IncomingTransfer>>>> {
+        Ok(slots.iter()
+            .map(|slot| Ok(self.watch.take_transfers(*slot)))
+            .collect())
+    }
+
+    async fn get_blocks(&self, start_slot: Slot, end_slot: Slot, commitment: Commitment) -> anyhow::Result<Vec<Slot>> {
+        self.sync(start_slot, commitment)
+            .await
+            .context("err self.sync() in get_blocks()")?;
+
+        self.watch.drop_before(start_slot);
+
+        Ok(self.watch.slots(start_slot, end_slot))
+    }
+
+    async fn get_slot(&self, commitment: Commitment) -> anyhow::Result<Slot> {
+        self.block_service.get_slot(commitment)
+            .await
+            .context("err block_service.get_slot() in get_slot()")
+    }
+}
@@ -0,0 +1,7 @@
+mod address;
+pub use address::*;
+
+pub mod pending;
+pub mod poll;
+pub mod res;
+pub mod watch;
\ No newline at end of file
@@ -0,0 +1,72 @@
+use std::{collections::HashSet, sync::{Arc, PoisonError, RwLock, RwLockReadGuard}};
+
+use app::domain::address::AddressEvent;
+use tokio::{select, sync::mpsc::UnboundedReceiver, task};
+use tokio_util::sync::CancellationToken;
+
+use crate::service::parser::to_address;
+
+/// Read-mostly set of pending payment addresses, blocks are filtered against it
+/// before any transfer is built
+#[derive(Clone, Default)]
+pub struct PendingAddresses {
+    addresses: Arc<RwLock<HashSet<String>>>,
+}
+
+impl PendingAddresses {
+    #[inline]
+    pub fn insert(&self, address: String) {
+        self.addresses.write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(address);
+    }
+
+    #[inline]
+    pub fn remove(&self, address: &str) {
+        self.addresses.write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(address);
+    }
+
+    /// Holds the read lock, so a whole block is checked with a single lock
+    #[inline]
+    pub fn read(&self) -> RwLockReadGuard<'_, HashSet<String>> {
+        self.addresses.read().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Keeps `PendingAddresses` in sync with the payments published by the `PaymentsActor`
+pub struct PendingAddressesActor {
+    address_rx: UnboundedReceiver<AddressEvent>,
+    addresses: PendingAddresses,
+}
+
+impl PendingAddressesActor {
+    pub fn new(address_rx: UnboundedReceiver<AddressEvent>, addresses: PendingAddresses) -> Self {
+        Self { address_rx, addresses }
+    }
+
+    pub async fn start(mut self, token: CancellationToken) {
+        loop {
+            select! {
+                Some(event) = self.address_rx.recv() => self.process_address_event(event),
+
+                _ = token.cancelled() => return,
+
+                _ = task::yield_now() => continue,
+            }
+        }
+    }
+
+    fn process_address_event(&self, event: AddressEvent) {
+        match event {
+            AddressEvent::Pending(pubkey) => if let Some(address) = to_address(&pubkey) {
+                self.addresses.insert(address);
+            },
+
+            AddressEvent::Completed(pubkey) => if let Some(address) = to_address(&pubkey) {
+                self.addresses.remove(&address);
+            },
+        }
+    }
+}
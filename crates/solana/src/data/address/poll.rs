@@ -0,0 +1,52 @@
+use std::time::Duration;
+
+use app::domain::address::AddressEvent;
+use tokio::{select, sync::mpsc::UnboundedReceiver, task, time::{interval, MissedTickBehavior}};
+use tokio_util::sync::CancellationToken;
+
+use crate::service::parser::to_address;
+
+use super::watch::AddressWatch;
+
+/// Marks every pending payment address as changed once per `interval`, so `AddressRepo` looks up
+/// their new signatures without any subscription
+pub struct AddressPollActor {
+    address_rx: UnboundedReceiver<AddressEvent>,
+    watch: AddressWatch,
+    interval: Duration,
+}
+
+impl AddressPollActor {
+    pub fn new(address_rx: UnboundedReceiver<AddressEvent>, watch: AddressWatch, interval: Duration) -> Self {
+        Self { address_rx, watch, interval }
+    }
+
+    pub async fn start(mut self, token: CancellationToken) {
+        let mut poll_interval = interval(self.interval);
+        poll_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            select! {
+                Some(event) = self.address_rx.recv() => self.process_address_event(event),
+
+                _ = poll_interval.tick() => self.watch.mark_all_changed(),
+
+                _ = token.cancelled() => return,
+
+                _ = task::yield_now() => continue,
+            }
+        }
+    }
+
+    fn process_address_event(&self, event: AddressEvent) {
+        match event {
+            AddressEvent::Pending(pubkey) => if let Some(address) = to_address(&pubkey) {
+                self.watch.watch(address);
+            },
+
+            AddressEvent::Completed(pubkey) => if let Some(address) = to_address(&pubkey) {
+                self.watch.unwatch(&address);
+            },
+        }
+    }
+}
@@ -0,0 +1,12 @@
+use sonic_rs::{Deserialize, Value};
+
+use crate::domain::slot::Slot;
+
+#[derive(Deserialize)]
+pub struct SignatureInfo {
+    pub signature: String,
+    pub slot: Slot,
+
+    #[serde(default)]
+    pub err: Option<Value>,
+}
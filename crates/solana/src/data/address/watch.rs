@@ -0,0 +1,124 @@
+use std::{collections::{BTreeMap, HashMap}, sync::{Arc, Mutex, MutexGuard, PoisonError}};
+
+use app::domain::transfer::IncomingTransfer;
+
+use crate::domain::slot::Slot;
+
+/// Addresses of pending payments together with the transfers found for them.
+/// Watchers mark addresses as changed, the `AddressRepo` looks the changes up and
+/// buffers the transfers by slot until the sequencer reaches them.
+#[derive(Clone, Default)]
+pub struct AddressWatch {
+    state: Arc<Mutex<WatchState>>,
+}
+
+#[derive(Default)]
+struct WatchState {
+    addresses: HashMap<String, AddressState>,
+    transfers: BTreeMap<Slot, Vec<IncomingTransfer>>,
+}
+
+#[derive(Default)]
+struct AddressState {
+    /// Newest signature looked up, later lookups stop at it
+    last_signature: Option<String>,
+    changed: bool,
+}
+
+impl AddressWatch {
+    /// Returns false if the address is already watched. A new address is looked up right away,
+    /// so transfers sent before the watch started are not missed.
+    pub fn watch(&self, address: String) -> bool {
+        let mut state = self.state();
+
+        if state.addresses.contains_key(&address) {
+            return false;
+        }
+
+        state.addresses.insert(address, AddressState { last_signature: None, changed: true });
+        true
+    }
+
+    #[inline]
+    pub fn unwatch(&self, address: &str) {
+        self.state().addresses.remove(address);
+    }
+
+    #[inline]
+    pub fn is_watched(&self, address: &str) -> bool {
+        self.state().addresses.contains_key(address)
+    }
+
+    pub fn addresses(&self) -> Vec<String> {
+        self.state().addresses
+            .keys()
+            .cloned()
+            .collect()
+    }
+
+    pub fn mark_changed(&self, address: &str) {
+        if let Some(a) = self.state().addresses.get_mut(address) {
+            a.changed = true;
+        }
+    }
+
+    pub fn mark_all_changed(&self) {
+        for a in self.state().addresses.values_mut() {
+            a.changed = true;
+        }
+    }
+
+    /// Returns changed addresses with the signature their lookup stops at and clears the flag
+    pub fn take_changed(&self) -> Vec<(String, Option<String>)> {
+        self.state().addresses
+            .iter_mut()
+            .filter(|(_, a)| a.changed)
+            .map(|(address, a)| {
+                a.changed = false;
+                (address.clone(), a.last_signature.clone())
+            })
+            .collect()
+    }
+
+    pub fn set_last_signature(&self, address: &str, signature: String) {
+        if let Some(a) = self.state().addresses.get_mut(address) {
+            a.last_signature = Some(signature);
+        }
+    }
+
+    pub fn push_transfers(&self, slot: Slot, transfers: Vec<IncomingTransfer>) {
+        if transfers.is_empty() {
+            return;
+        }
+
+        self.state().transfers
+            .entry(slot)
+            .or_default()
+            .extend(transfers);
+    }
+
+    /// Returns slots between `start_slot` and `end_slot` inclusive which hold transfers
+    pub fn slots(&self, start_slot: Slot, end_slot: Slot) -> Vec<Slot> {
+        self.state().transfers
+            .range(start_slot..=end_slot)
+            .map(|(slot, _)| *slot)
+            .collect()
+    }
+
+    #[inline]
+    pub fn take_transfers(&self, slot: Slot) -> Vec<IncomingTransfer> {
+        self.state().transfers
+            .remove(&slot)
+            .unwrap_or_default()
+    }
+
+    /// Drops transfers the sequencer has already moved past
+    pub fn drop_before(&self, slot: Slot) {
+        let mut state = self.state();
+        state.transfers = state.transfers.split_off(&slot);
+    }
+
+    fn state(&self) -> MutexGuard<'_, WatchState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
@@ -1,58 +1,390 @@
-use anyhow::Context;
+use std::{collections::HashMap, io::Read, time::Duration};
+
+use anyhow::{anyhow, Context};
 use app::domain::transfer::IncomingTransfer;
 use const_format::concatcp;
+use flate2::read::GzDecoder;
 use http_body_util::{BodyExt, Full};
-use hyper::{body::Bytes, Method, Request};
+use hyper::{body::Bytes, header::{ACCEPT_ENCODING, CONTENT_ENCODING, RETRY_AFTER}, Method, Request, StatusCode};
 use hyper_util::client::legacy::{connect::HttpConnector, Client};
+use log::{error, info};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::time::Instant;
+
+use crate::{data::{address::pending::PendingAddresses, endpoint::{EndpointId, EndpointPool}, error::RpcError, req::{Commitment, Encoding, RpcParameter, RpcReq, TransactionDetails}, res::{from_batch_slice, RpcId, RpcRes}}, domain::slot::Slot};
+
+use super::{parsed::{GetParsedBlockRes, ParsedTransactionRes}, res::{GetBlockRes, GetTransactionRes}};
+
+/// Number of times a throttled request is repeated before giving up
+const MAX_RATE_LIMITED_RETRIES: u32 = 3;
+/// Used when a 429 response comes without `Retry-After`
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(1);
 
-use crate::{data::{req::RpcReq, res::RpcRes}, domain::slot::Slot};
+/// How SOL credits are read from a block
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TransferParsing {
+    /// Balance diffs of every account
+    Balances,
+    /// System Program transfers with `jsonParsed` encoding, which also tell the source
+    Instructions,
+}
+
+/// A `getBlock` response layout, each one is read by its own [`TransferParsing`]
+trait BlockLayout<'a>: Deserialize<'a> {
+    fn transfers<F: Fn(&str) -> bool>(self, skip_failed: bool, is_pending: F) -> Vec<IncomingTransfer>;
+}
+
+impl<'a> BlockLayout<'a> for GetBlockRes<'a> {
+    #[inline]
+    fn transfers<F: Fn(&str) -> bool>(self, skip_failed: bool, is_pending: F) -> Vec<IncomingTransfer> {
+        self.into_transfers(skip_failed, is_pending)
+    }
+}
+
+impl<'a> BlockLayout<'a> for GetParsedBlockRes<'a> {
+    #[inline]
+    fn transfers<F: Fn(&str) -> bool>(self, skip_failed: bool, is_pending: F) -> Vec<IncomingTransfer> {
+        self.into_transfers(skip_failed, is_pending)
+    }
+}
 
-use super::res::GetBlockRes;
+type BlockResult = Result<Vec<IncomingTransfer>, RpcError>;
 
 #[trait_variant::make(BlockRepo: Send)]
 pub trait LocalBlockRepo {
-    async fn get_block(&self, slot: Slot) -> anyhow::Result<Vec<IncomingTransfer>>;
+    async fn get_block(&self, slot: Slot, commitment: Commitment) -> anyhow::Result<Vec<IncomingTransfer>>;
+
+    /// Fetches blocks in a single round trip, results follow the order of `slots`
+    async fn get_block_batch(&self, slots: &[Slot], commitment: Commitment) -> anyhow::Result<Vec<anyhow::Result<Vec<IncomingTransfer>>>>;
+
+    /// Returns blocks between `start_slot` and `end_slot` inclusive, skipped slots are omitted
+    async fn get_blocks(&self, start_slot: Slot, end_slot: Slot, commitment: Commitment) -> anyhow::Result<Vec<Slot>>;
+
+    /// Returns the latest slot at the commitment
+    async fn get_slot(&self, commitment: Commitment) -> anyhow::Result<Slot>;
 }
 
 pub struct BlockService {
     client: Client<HttpConnector, Full<Bytes>>,
-    endpoint_url: String,
+    endpoints: EndpointPool,
+    skip_failed_transactions: bool,
+    pending: Option<PendingAddresses>,
+    transaction_details: TransactionDetails,
+    parsing: TransferParsing,
 }
 
 impl BlockService {
-    pub fn new(client: Client<HttpConnector, Full<Bytes>>, endpoint_url: String) -> Self {
-        Self { client, endpoint_url }
+    pub fn new(
+        client: Client<HttpConnector, Full<Bytes>>,
+        endpoints: EndpointPool,
+        skip_failed_transactions: bool,
+    ) -> Self {
+        Self {
+            client, endpoints,
+            skip_failed_transactions,
+            pending: None,
+            transaction_details: TransactionDetails::Full,
+            parsing: TransferParsing::Balances,
+        }
     }
-}
 
-impl BlockRepo for BlockService {
-    async fn get_block(&self, slot: Slot) -> anyhow::Result<Vec<IncomingTransfer>> {
-        const FN_CTX: &str = "get_block()";
+    /// With [`TransferParsing::Instructions`] whole transactions are requested, `transaction_details` is ignored
+    pub fn with_transfer_parsing(mut self, parsing: TransferParsing) -> Self {
+        self.parsing = parsing;
+        self
+    }
+
+    /// Sets how much of every transaction `getBlock` returns
+    pub fn with_transaction_details(mut self, transaction_details: TransactionDetails) -> Self {
+        self.transaction_details = transaction_details;
+        self
+    }
+
+    /// Drops credits of addresses without a pending payment while the block is mapped
+    pub fn with_pending_addresses(mut self, pending: PendingAddresses) -> Self {
+        self.pending = Some(pending);
+        self
+    }
+
+    #[inline]
+    pub fn skip_failed_transactions(&self) -> bool {
+        self.skip_failed_transactions
+    }
 
-        let req = RpcReq::new_get_block(slot);
-        let payload = sonic_rs::to_vec(&req)
-            .context(concatcp!("err sonic_rs::to_vec() in ", FN_CTX))?;
+    /// Sends the request to the best endpoint, failing over to the next one until every endpoint was tried.
+    /// `weight` is the number of calls in the request, which is taken from the rate limit of the endpoint.
+    /// The response body is handed to `parse` while it is still alive, so it may be deserialized into borrowed values,
+    /// a response failing to parse is treated like a failed request.
+    async fn send<T, R, F>(&self, req: &T, weight: u32, parse: F) -> anyhow::Result<R>
+    where
+        T: Serialize,
+        F: Fn(&[u8]) -> anyhow::Result<R>,
+    {
+        const FN_CTX: &str = "send()";
+
+        let payload = Bytes::from(
+            sonic_rs::to_vec(req)
+                .context(concatcp!("err sonic_rs::to_vec() in ", FN_CTX))?
+        );
+
+        let mut tried = Vec::with_capacity(self.endpoints.len());
+        let mut rate_limited = 0;
+
+        loop {
+            let endpoint = self.endpoints.select(&tried);
+
+            if !tried.contains(&endpoint) {
+                tried.push(endpoint);
+            }
+
+            self.endpoints.acquire(endpoint, weight).await;
+
+            let started_at = Instant::now();
+
+            let res = self.send_to(endpoint, payload.clone())
+                .await
+                .and_then(|b| parse(&b));
+
+            let e = match res {
+                Ok(res) => {
+                    self.endpoints.report_success(endpoint, started_at.elapsed());
+                    return Ok(res);
+                },
+
+                Err(e) => e,
+            };
+
+            match e.downcast_ref::<RpcError>() {
+                // Throttling says nothing about the health of the endpoint, it is only held back
+                Some(RpcError::RateLimited { retry_after }) if rate_limited < MAX_RATE_LIMITED_RETRIES => {
+                    let delay = retry_after.unwrap_or(DEFAULT_RETRY_AFTER);
+
+                    rate_limited += 1;
+                    self.endpoints.throttle(endpoint, delay);
+
+                    info!("[{}] - rate limited, endpoint throttled for {:?}", FN_CTX, delay);
+                },
+
+                Some(RpcError::RateLimited { .. }) => return Err(e.context(concatcp!("err self.send_to() in ", FN_CTX))),
+
+                _ if tried.len() < self.endpoints.len() => {
+                    self.endpoints.report_failure(endpoint);
+                    error!("err self.send_to() in {}, failing over: {:#?}", FN_CTX, e);
+                },
+
+                _ => {
+                    self.endpoints.report_failure(endpoint);
+                    return Err(e.context(concatcp!("err self.send_to() in ", FN_CTX)));
+                },
+            }
+        }
+    }
+
+    async fn send_to(&self, endpoint: EndpointId, payload: Bytes) -> anyhow::Result<Bytes> {
+        const FN_CTX: &str = "send_to()";
 
         let req = Request::builder()
-            .uri(&self.endpoint_url)
+            .uri(self.endpoints.http_url(endpoint))
             .method(Method::POST)
             .header("Content-Type", "application/json")
-            .body(Full::new(Bytes::from(payload)))
+            .header(ACCEPT_ENCODING, "gzip")
+            .body(Full::new(payload))
             .context(concatcp!("err Request::builder() in ", FN_CTX))?;
 
         let res = self.client.request(req)
             .await
             .context(concatcp!("err client.request() in ", FN_CTX))?;
 
+        match res.status() {
+            StatusCode::TOO_MANY_REQUESTS => {
+                let retry_after = res.headers()
+                    .get(RETRY_AFTER)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.trim().parse().ok())
+                    .map(Duration::from_secs);
+
+                return Err(RpcError::RateLimited { retry_after }.into());
+            },
+
+            status if !status.is_success() => return Err(RpcError::Status(status).into()),
+
+            _ => (),
+        }
+
+        let gzip = res.headers()
+            .get(CONTENT_ENCODING)
+            .is_some_and(|v| v.as_bytes().eq_ignore_ascii_case(b"gzip"));
+
         let b = res
             .collect()
             .await
             .context(concatcp!("err res.collect() in ", FN_CTX))?
             .to_bytes();
 
-        let block: RpcRes<GetBlockRes> = sonic_rs::from_slice(&b)
-            .context(concatcp!("err sonic_rs::from_slice() in ", FN_CTX))?;
+        if !gzip {
+            return Ok(b);
+        }
+
+        let mut decoded = Vec::with_capacity(b.len() * 4);
+        GzDecoder::new(&b[..]).read_to_end(&mut decoded)
+            .context(concatcp!("err GzDecoder::read_to_end() in ", FN_CTX))?;
+
+        Ok(decoded.into())
+    }
 
-        Ok(block.result.into())
+    fn new_get_block(&self, slot: Slot, commitment: Commitment) -> RpcReq<'static, [RpcParameter; 2]> {
+        match self.parsing {
+            TransferParsing::Balances => RpcReq::new_get_block(slot, commitment, Encoding::Json, self.transaction_details),
+            TransferParsing::Instructions => RpcReq::new_get_block(slot, commitment, Encoding::JsonParsed, TransactionDetails::Full),
+        }
     }
-}
\ No newline at end of file
+
+    fn parse_block(&self, b: &[u8]) -> anyhow::Result<BlockResult> {
+        match self.parsing {
+            TransferParsing::Balances => self.parse_layout::<GetBlockRes>(b),
+            TransferParsing::Instructions => self.parse_layout::<GetParsedBlockRes>(b),
+        }
+    }
+
+    fn parse_block_batch(&self, b: &[u8]) -> anyhow::Result<HashMap<RpcId, BlockResult>> {
+        match self.parsing {
+            TransferParsing::Balances => self.parse_layout_batch::<GetBlockRes>(b),
+            TransferParsing::Instructions => self.parse_layout_batch::<GetParsedBlockRes>(b),
+        }
+    }
+
+    fn parse_layout<'a, B: BlockLayout<'a>>(&self, b: &'a [u8]) -> anyhow::Result<BlockResult> {
+        let res: RpcRes<B> = sonic_rs::from_slice(b)
+            .context("err sonic_rs::from_slice() in parse_layout()")?;
+
+        Ok(res.into_result().map(|block| self.map_block(block)))
+    }
+
+    /// Batch responses may come in any order, so they are keyed by the slot used as an id
+    fn parse_layout_batch<'a, B: BlockLayout<'a>>(&self, b: &'a [u8]) -> anyhow::Result<HashMap<RpcId, BlockResult>> {
+        let res = from_batch_slice::<B>(b)
+            .context("err from_batch_slice() in parse_layout_batch()")?;
+
+        Ok(res.into_iter()
+            .map(|(id, r)| (id, r.map(|block| self.map_block(block))))
+            .collect())
+    }
+
+    fn map_block<'a, B: BlockLayout<'a>>(&self, block: B) -> Vec<IncomingTransfer> {
+        match &self.pending {
+            Some(pending) => {
+                let pending = pending.read();
+                block.transfers(self.skip_failed_transactions, |a| pending.contains(a))
+            },
+
+            None => block.transfers(self.skip_failed_transactions, |_| true),
+        }
+    }
+
+    /// Fetches a single transaction in the layout of the [`TransferParsing`], so it credits the same amounts as its block
+    pub(crate) async fn get_transaction<F>(&self, signature: String, commitment: Commitment, is_pending: F) -> anyhow::Result<Vec<IncomingTransfer>>
+    where
+        F: Fn(&str) -> bool,
+    {
+        const FN_CTX: &str = "get_transaction()";
+
+        let req = match self.parsing {
+            TransferParsing::Balances => RpcReq::new_get_transaction(signature, commitment, Encoding::Json),
+            TransferParsing::Instructions => RpcReq::new_get_transaction(signature, commitment, Encoding::JsonParsed),
+        };
+
+        self.call_with(&req, |b| match self.parsing {
+            TransferParsing::Balances => {
+                let res: RpcRes<GetTransactionRes> = sonic_rs::from_slice(b)
+                    .context(concatcp!("err sonic_rs::from_slice() in ", FN_CTX))?;
+
+                Ok(res.into_result()?.into_transfers(self.skip_failed_transactions, &is_pending))
+            },
+
+            TransferParsing::Instructions => {
+                let res: RpcRes<ParsedTransactionRes> = sonic_rs::from_slice(b)
+                    .context(concatcp!("err sonic_rs::from_slice() in ", FN_CTX))?;
+
+                Ok(res.into_result()?.into_transfers(self.skip_failed_transactions, &is_pending))
+            },
+        })
+        .await
+        .context(concatcp!("err self.call_with() in ", FN_CTX))
+    }
+
+    /// Sends a single call and unwraps its result, the `error` field is returned as [`RpcError::Rpc`]
+    pub(crate) async fn call<T: Serialize, R: DeserializeOwned>(&self, req: &T) -> anyhow::Result<R> {
+        let res: RpcRes<R> = self.send(req, 1, |b| sonic_rs::from_slice(b).context("err sonic_rs::from_slice() in call()"))
+            .await
+            .context("err self.send() in call()")?;
+
+        Ok(res.into_result()?)
+    }
+
+    /// Sends a single call, `parse` deserializes the response body and maps the borrowed result into an owned value
+    pub(crate) async fn call_with<T, R, F>(&self, req: &T, parse: F) -> anyhow::Result<R>
+    where
+        T: Serialize,
+        F: Fn(&[u8]) -> anyhow::Result<R>,
+    {
+        self.send(req, 1, parse)
+            .await
+            .context("err self.send() in call_with()")
+    }
+}
+
+impl BlockRepo for BlockService {
+    async fn get_block(&self, slot: Slot, commitment: Commitment) -> anyhow::Result<Vec<IncomingTransfer>> {
+        const FN_CTX: &str = "get_block()";
+
+        let transfers = self.call_with(&self.new_get_block(slot, commitment), |b| self.parse_block(b))
+            .await
+            .context(concatcp!("err self.call_with() in ", FN_CTX))?;
+
+        match transfers {
+            Ok(transfers) => Ok(transfers),
+            Err(e) if e.is_slot_skipped() => Ok(Vec::new()),
+            Err(e) => Err(anyhow!("err res.into_result() in {}: {}", FN_CTX, e)),
+        }
+    }
+
+    async fn get_block_batch(&self, slots: &[Slot], commitment: Commitment) -> anyhow::Result<Vec<anyhow::Result<Vec<IncomingTransfer>>>> {
+        const FN_CTX: &str = "get_block_batch()";
+
+        let reqs: Vec<_> = slots.iter()
+            .map(|slot| self.new_get_block(*slot, commitment).with_id(*slot))
+            .collect();
+
+        // Blocks are mapped while the response body is alive, as they borrow from it
+        let mut blocks = self.send(&reqs, reqs.len() as u32, |b| self.parse_block_batch(b))
+            .await
+            .context(concatcp!("err self.send() in ", FN_CTX))?;
+
+        let blocks = slots.iter()
+            .map(|slot| {
+                let block = blocks.remove(slot)
+                    .unwrap_or(Err(RpcError::MissingResult));
+
+                match block {
+                    Ok(transfers) => Ok(transfers),
+                    Err(e) if e.is_slot_skipped() => Ok(Vec::new()),
+                    Err(e) => Err(anyhow!("err block.into_result() in {}, slot: {}: {}", FN_CTX, slot, e)),
+                }
+            })
+            .collect();
+
+        Ok(blocks)
+    }
+
+    async fn get_blocks(&self, start_slot: Slot, end_slot: Slot, commitment: Commitment) -> anyhow::Result<Vec<Slot>> {
+        self.call(&RpcReq::new_get_blocks(start_slot, end_slot, commitment))
+            .await
+            .context("err self.call() in get_blocks()")
+    }
+
+    async fn get_slot(&self, commitment: Commitment) -> anyhow::Result<Slot> {
+        self.call(&RpcReq::new_get_slot(commitment))
+            .await
+            .context("err self.call() in get_slot()")
+    }
+}
@@ -1,7 +1,7 @@
 use app::domain::transfer::{IncomingTransfer, TransferData};
 use tokio::sync::Mutex;
 
-use crate::domain::slot::Slot;
+use crate::{data::req::Commitment, domain::slot::Slot};
 
 use super::BlockRepo;
 
@@ -30,7 +30,7 @@ impl BlockServiceMock {
 }
 
 impl BlockRepo for BlockServiceMock {
-    async fn get_block(&self, _slot: Slot) -> anyhow::Result<Vec<IncomingTransfer>> {
+    async fn get_block(&self, _slot: Slot, _commitment: Commitment) -> anyhow::Result<Vec<IncomingTransfer>> {
         let address = self.meta
             .lock()
             .await
@@ -39,11 +39,29 @@ impl BlockRepo for BlockServiceMock {
         Ok(match address {
             Some(m) => vec![
                 IncomingTransfer::new(
-                    TransferData::new(m.address, m.amount),
+                    TransferData::new(m.address, m.amount, None),
                     vec![m.signature],
                 )
             ],
             None => Vec::new(),
         })
     }
+
+    async fn get_block_batch(&self, slots: &[Slot], commitment: Commitment) -> anyhow::Result<Vec<anyhow::Result<Vec<IncomingTransfer>>>> {
+        let mut blocks = Vec::with_capacity(slots.len());
+
+        for slot in slots {
+            blocks.push(self.get_block(*slot, commitment).await);
+        }
+
+        Ok(blocks)
+    }
+
+    async fn get_blocks(&self, start_slot: Slot, end_slot: Slot, _commitment: Commitment) -> anyhow::Result<Vec<Slot>> {
+        Ok((start_slot..=end_slot).collect())
+    }
+
+    async fn get_slot(&self, _commitment: Commitment) -> anyhow::Result<Slot> {
+        Ok(Slot::MAX)
+    }
 }
\ No newline at end of file
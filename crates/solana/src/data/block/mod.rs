@@ -1,5 +1,6 @@
 mod block;
 pub use block::*;
 
+pub mod parsed;
 pub mod res;
 pub mod mock;
\ No newline at end of file
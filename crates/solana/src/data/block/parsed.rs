@@ -0,0 +1,249 @@
+use std::borrow::Cow;
+
+use app::domain::transfer::{IncomingTransfer, TransferData};
+use log::debug;
+use serde::de::IgnoredAny;
+use sonic_rs::{Deserialize, Value};
+
+use super::res::{token_transfers, to_owned, AccountKey, TokenBalance, MEMO_PROGRAMS};
+
+const SYSTEM_PROGRAM: &str = "system";
+
+/// `getBlock` response with `jsonParsed` encoding. SOL credits are read from System Program
+/// transfers, inner instructions included, so fee refunds, rent and closed accounts are not
+/// taken for payments and every transfer knows its source.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetParsedBlockRes<'a> {
+    #[serde(default)]
+    block_time: Option<i64>,
+
+    #[serde(borrow)]
+    transactions: Vec<ParsedTransactionRes<'a>>,
+}
+
+impl GetParsedBlockRes<'_> {
+    /// Only credits of addresses accepted by `is_pending` are turned into transfers
+    pub fn into_transfers<F: Fn(&str) -> bool>(self, skip_failed: bool, is_pending: F) -> Vec<IncomingTransfer> {
+        let block_time = self.block_time;
+
+        // Transactions of a block carry no time of their own
+        self.transactions
+            .into_iter()
+            .flat_map(|mut tx| {
+                tx.block_time = block_time;
+                tx.into_transfers(skip_failed, &is_pending)
+            })
+            .collect()
+    }
+}
+
+impl ParsedTransactionRes<'_> {
+    /// Instructions of a failed transaction were rolled back, so they are never credited,
+    /// `skip_failed` only decides whether it is logged
+    pub fn into_transfers<F: Fn(&str) -> bool>(self, skip_failed: bool, is_pending: F) -> Vec<IncomingTransfer> {
+        const FN_CTX: &str = "ParsedTransactionRes::into_transfers()";
+
+        let tx_data = self.transaction;
+        let block_time = self.block_time;
+
+        if let Some(err) = &self.meta.err {
+            if skip_failed {
+                debug!("[{}] - skip failed tx: {:?}, err: {}", FN_CTX, tx_data.signatures.first(), err);
+            }
+
+            return Vec::new();
+        }
+
+        // Cloning a borrowed memo is free, it is only copied once a transfer is found
+        let memo = tx_data.message.instructions
+            .iter()
+            .find_map(|i| i.memo());
+
+        let inner = self.meta.inner_instructions
+            .unwrap_or_default()
+            .into_iter()
+            .flat_map(|i| i.instructions);
+
+        // Payments take one credit per transaction, so transfers to the same address are summed
+        let mut credits: Vec<SystemTransfer> = Vec::new();
+
+        let system_transfers = tx_data.message.instructions
+            .into_iter()
+            .chain(inner)
+            .filter_map(|i| i.system_transfer())
+            .filter(|t| t.lamports > 0 && is_pending(t.destination));
+
+        for t in system_transfers {
+            match credits.iter_mut().find(|c| c.destination == t.destination) {
+                Some(c) => c.lamports += t.lamports,
+                None => credits.push(t),
+            }
+        }
+
+        let mut transfers: Vec<_> = credits
+            .into_iter()
+            .map(|t| IncomingTransfer::new(
+                TransferData::new(t.destination.to_string(), t.lamports, None)
+                    .with_source(Some(t.source.to_string())),
+                to_owned(&tx_data.signatures),
+            ))
+            .collect();
+
+        let (pre, post) = (self.meta.pre_token_balances, self.meta.post_token_balances);
+        transfers.extend(token_transfers(pre.unwrap_or_default(), post.unwrap_or_default(), &is_pending, &tx_data.signatures));
+
+        if transfers.is_empty() {
+            return transfers;
+        }
+
+        let memo = memo.map(Cow::into_owned);
+        let account_keys: Vec<_> = tx_data.message.account_keys
+            .iter()
+            .map(|k| k.pubkey.to_string())
+            .collect();
+
+        transfers.into_iter()
+            .map(|t| t
+                .with_block_time(block_time)
+                .with_memo(memo.clone())
+                .with_account_keys(account_keys.clone()))
+            .collect()
+    }
+}
+
+/// `blockTime` is only set by `getTransaction`, `getBlock` has it on the block
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ParsedTransactionRes<'a> {
+    #[serde(default)]
+    block_time: Option<i64>,
+
+    #[serde(borrow)]
+    meta: ParsedMeta<'a>,
+
+    #[serde(borrow)]
+    transaction: ParsedTransaction<'a>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ParsedMeta<'a> {
+    #[serde(default)]
+    err: Option<Value>,
+
+    #[serde(default, borrow)]
+    inner_instructions: Option<Vec<InnerInstructions<'a>>>,
+
+    #[serde(default, borrow)]
+    post_token_balances: Option<Vec<TokenBalance<'a>>>,
+
+    #[serde(default, borrow)]
+    pre_token_balances: Option<Vec<TokenBalance<'a>>>,
+}
+
+#[derive(Deserialize)]
+pub struct InnerInstructions<'a> {
+    #[serde(borrow)]
+    instructions: Vec<Instruction<'a>>,
+}
+
+#[derive(Deserialize)]
+pub struct ParsedTransaction<'a> {
+    #[serde(borrow)]
+    message: ParsedMessage<'a>,
+
+    #[serde(borrow)]
+    signatures: Vec<&'a str>,
+}
+
+/// `jsonParsed` lists every account key, lookup table ones included
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ParsedMessage<'a> {
+    #[serde(borrow)]
+    account_keys: Vec<AccountKey<'a>>,
+
+    #[serde(borrow)]
+    instructions: Vec<Instruction<'a>>,
+}
+
+/// `parsed` is an object for most programs but a plain string for some, like the Memo program
+#[derive(Deserialize)]
+pub struct Instruction<'a> {
+    #[serde(default)]
+    program: Option<&'a str>,
+
+    #[serde(default, rename = "programId")]
+    program_id: Option<&'a str>,
+
+    #[serde(default, borrow)]
+    parsed: Option<Parsed<'a>>,
+}
+
+impl<'a> Instruction<'a> {
+    fn memo(&self) -> Option<Cow<'a, str>> {
+        match (&self.parsed, self.program_id) {
+            (Some(Parsed::Memo(memo)), Some(program_id)) if MEMO_PROGRAMS.contains(&program_id) => Some(memo.clone()),
+            _ => None,
+        }
+    }
+
+    fn system_transfer(self) -> Option<SystemTransfer<'a>> {
+        let Some(Parsed::Instruction(instruction)) = self.parsed else {
+            return None;
+        };
+
+        // Other instructions have different fields in `info`
+        match (self.program, instruction.kind) {
+            (Some(SYSTEM_PROGRAM), "transfer" | "transferWithSeed") => Some(SystemTransfer {
+                source: instruction.info.source?,
+                destination: instruction.info.destination?,
+                lamports: instruction.info.lamports?,
+            }),
+
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum Parsed<'a> {
+    #[serde(borrow)]
+    Instruction(ParsedInstruction<'a>),
+
+    /// The Memo program parses into the memo itself, escaped memos cannot be borrowed
+    #[serde(borrow)]
+    Memo(Cow<'a, str>),
+
+    Other(IgnoredAny),
+}
+
+#[derive(Deserialize)]
+pub struct ParsedInstruction<'a> {
+    #[serde(rename = "type")]
+    kind: &'a str,
+
+    #[serde(default, borrow)]
+    info: TransferInfo<'a>,
+}
+
+/// Fields of `transfer` and `transferWithSeed`, missing for other instructions
+#[derive(Deserialize, Default)]
+pub struct TransferInfo<'a> {
+    #[serde(default)]
+    source: Option<&'a str>,
+
+    #[serde(default)]
+    destination: Option<&'a str>,
+
+    #[serde(default)]
+    lamports: Option<u64>,
+}
+
+struct SystemTransfer<'a> {
+    source: &'a str,
+    destination: &'a str,
+    lamports: u64,
+}
@@ -1,57 +1,324 @@
 use app::domain::transfer::{IncomingTransfer, TransferData};
-use itertools::izip;
-use sonic_rs::Deserialize;
+use itertools::{izip, Either};
+use log::{debug, error};
+use sonic_rs::{Deserialize, Value};
 
+/// Program ids of both versions of the Memo program
+pub(super) const MEMO_PROGRAMS: [&str; 2] = [
+    "MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr",
+    "Memo1UhkJRfHyvLMcVucJwxXeuD728EqVDDwQDxFMNo",
+];
+
+/// Borrows every string from the response body, only credits which are kept are copied.
+/// Base58 addresses and signatures never contain escapes, so they can always be borrowed.
 #[derive(Deserialize)]
-pub struct GetBlockRes {
-    transactions: Vec<GetTransactionRes>,
+#[serde(rename_all = "camelCase")]
+pub struct GetBlockRes<'a> {
+    #[serde(default)]
+    block_time: Option<i64>,
+
+    #[serde(borrow)]
+    transactions: Vec<GetTransactionRes<'a>>,
+}
+
+impl GetBlockRes<'_> {
+    /// Only credits of addresses accepted by `is_pending` are turned into transfers
+    pub fn into_transfers<F: Fn(&str) -> bool>(self, skip_failed: bool, is_pending: F) -> Vec<IncomingTransfer> {
+        let block_time = self.block_time;
+
+        // Transactions of a block carry no time of their own
+        self.transactions
+            .into_iter()
+            .flat_map(|mut tx| {
+                tx.block_time = block_time;
+                tx.into_transfers(skip_failed, &is_pending)
+            })
+            .collect()
+    }
 }
 
-impl Into<Vec<IncomingTransfer>> for GetBlockRes {
-    fn into(self) -> Vec<IncomingTransfer> {
-        let mut transfers = Vec::new();
+impl GetTransactionRes<'_> {
+    pub fn into_transfers<F: Fn(&str) -> bool>(self, skip_failed: bool, is_pending: F) -> Vec<IncomingTransfer> {
+        const FN_CTX: &str = "GetTransactionRes::into_transfers()";
 
-        for tx in self.transactions {
-            let tx_data = tx.transaction;
-            let (pre, post) = (tx.meta.pre_balances, tx.meta.post_balances);
+        let tx_data = self.transaction;
+        let block_time = self.block_time;
 
-            let tx_transfers = izip!(tx_data.message.account_keys, pre, post)
-                .filter_map(|(addr, pre, post)| match pre < post {
-                    true => Some(IncomingTransfer::new(
-                        TransferData::new(addr, post - pre),
-                        tx_data.signatures.clone(),
-                    )),
-                    false => None,
-                });
+        // Instructions only come with `full` details, the memo is decoded once a transfer is found
+        let memo_data = tx_data.message
+            .as_ref()
+            .and_then(|m| m.memo_data());
 
-            transfers.extend(tx_transfers);
+        if let (Some(err), true) = (&self.meta.err, skip_failed) {
+            debug!("[{}] - skip failed tx: {:?}, err: {}", FN_CTX, tx_data.signatures.first(), err);
+            return Vec::new();
         }
 
-        transfers
+        let (pre, post) = (self.meta.pre_balances, self.meta.post_balances);
+
+        let account_keys: Vec<&str> = match (tx_data.message, tx_data.account_keys) {
+            // Balances are indexed by static keys, then writable and readonly lookup table keys
+            (Some(message), _) => {
+                let loaded = self.meta.loaded_addresses.unwrap_or_default();
+
+                Either::Left(message.account_keys
+                    .into_iter()
+                    .chain(loaded.writable)
+                    .chain(loaded.readonly))
+            },
+
+            // `accounts` details list every key in balance order, lookup table keys included
+            (None, keys) => Either::Right(keys
+                .unwrap_or_default()
+                .into_iter()
+                .map(|k| k.pubkey)),
+        }
+        .collect();
+
+        // The account losing the most lamports funded the credits, usually the fee payer
+        let source = izip!(&account_keys, &pre, &post)
+            .filter(|(_, pre, post)| pre > post)
+            .max_by_key(|(_, pre, post)| *pre - *post)
+            .map(|(addr, ..)| *addr);
+
+        let mut transfers: Vec<_> = izip!(&account_keys, &pre, &post)
+            .filter_map(|(addr, pre, post)| match pre < post && is_pending(addr) {
+                true => Some(IncomingTransfer::new(
+                    TransferData::new(addr.to_string(), post - pre, None)
+                        .with_source(source.map(str::to_string)),
+                    to_owned(&tx_data.signatures),
+                )),
+                false => None,
+            })
+            .collect();
+
+        let (pre, post) = (self.meta.pre_token_balances, self.meta.post_token_balances);
+        transfers.extend(token_transfers(pre.unwrap_or_default(), post.unwrap_or_default(), &is_pending, &tx_data.signatures));
+
+        if transfers.is_empty() {
+            return transfers;
+        }
+
+        let memo = memo_data.and_then(decode_memo);
+        let account_keys = to_owned(&account_keys);
+
+        transfers.into_iter()
+            .map(|t| t
+                .with_block_time(block_time)
+                .with_memo(memo.clone())
+                .with_account_keys(account_keys.clone()))
+            .collect()
     }
 }
 
+/// Token credits are balance diffs in every encoding, the token program moves balances of token accounts only
+pub(super) fn token_transfers<F: Fn(&str) -> bool>(
+    pre: Vec<TokenBalance>,
+    post: Vec<TokenBalance>,
+    is_pending: F,
+    signatures: &[&str],
+) -> Vec<IncomingTransfer> {
+    token_balance_changes(&pre, &post, is_pending)
+        .into_iter()
+        .filter_map(|c| match c.pre < c.post {
+            true => Some(IncomingTransfer::new(
+                TransferData::new(c.owner.to_string(), c.post - c.pre, Some(c.mint.to_string()))
+                    .with_source(token_source(&pre, &post, c.mint)),
+                to_owned(signatures),
+            )),
+            false => None,
+        })
+        .collect()
+}
+
+/// The owner losing the most of the mint funded the credit
+fn token_source(pre: &[TokenBalance], post: &[TokenBalance], mint: &str) -> Option<String> {
+    token_balance_changes(pre, post, |_| true)
+        .into_iter()
+        .filter(|c| c.mint == mint && c.pre > c.post)
+        .max_by_key(|c| c.pre - c.post)
+        .map(|c| c.owner.to_string())
+}
+
+#[inline]
+pub(super) fn to_owned(signatures: &[&str]) -> Vec<String> {
+    signatures.iter()
+        .map(|s| s.to_string())
+        .collect()
+}
+
+struct TokenBalanceChange<'a> {
+    owner: &'a str,
+    mint: &'a str,
+    pre: u64,
+    post: u64,
+}
+
+/// Sums token account balances by (owner, mint), so a wallet with several
+/// token accounts of the same mint is credited once per transaction.
+fn token_balance_changes<'a, F: Fn(&str) -> bool>(
+    pre: &[TokenBalance<'a>],
+    post: &[TokenBalance<'a>],
+    is_pending: F,
+) -> Vec<TokenBalanceChange<'a>> {
+    let mut changes: Vec<TokenBalanceChange> = Vec::new();
+
+    let balances = pre.iter()
+        .map(|b| (b, false))
+        .chain(post.iter().map(|b| (b, true)));
+
+    for (balance, is_post) in balances {
+        let Some(owner) = balance.owner.filter(|o| is_pending(o)) else {
+            continue;
+        };
+
+        let amount = match balance.ui_token_amount.amount.parse::<u64>() {
+            Ok(a) => a,
+            Err(e) => {
+                error!("err amount.parse() in token_balance_changes(): {}", e);
+                continue;
+            },
+        };
+
+        let i = match changes.iter().position(|c| c.owner == owner && c.mint == balance.mint) {
+            Some(i) => i,
+            None => {
+                changes.push(TokenBalanceChange { owner, mint: balance.mint, pre: 0, post: 0 });
+                changes.len() - 1
+            },
+        };
+
+        match is_post {
+            true => changes[i].post += amount,
+            false => changes[i].pre += amount,
+        }
+    }
+
+    changes
+}
+
+/// `blockTime` is only set by `getTransaction`, `getBlock` has it on the block
 #[derive(Deserialize)]
-pub struct GetTransactionRes {
-    meta: Meta,
-    transaction: Transaction,
+#[serde(rename_all = "camelCase")]
+pub struct GetTransactionRes<'a> {
+    #[serde(default)]
+    block_time: Option<i64>,
+
+    #[serde(borrow)]
+    meta: Meta<'a>,
+
+    #[serde(borrow)]
+    transaction: Transaction<'a>,
 }
 
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct Meta {
+pub struct Meta<'a> {
+    #[serde(default)]
+    err: Option<Value>,
+
     post_balances: Vec<u64>,
     pre_balances: Vec<u64>,
+
+    #[serde(default, borrow)]
+    post_token_balances: Option<Vec<TokenBalance<'a>>>,
+
+    #[serde(default, borrow)]
+    pre_token_balances: Option<Vec<TokenBalance<'a>>>,
+
+    #[serde(default, borrow)]
+    loaded_addresses: Option<LoadedAddresses<'a>>,
+}
+
+#[derive(Deserialize, Default)]
+pub struct LoadedAddresses<'a> {
+    #[serde(borrow)]
+    writable: Vec<&'a str>,
+
+    #[serde(borrow)]
+    readonly: Vec<&'a str>,
 }
 
 #[derive(Deserialize)]
-pub struct Transaction {
-    message: Message,
-    signatures: Vec<String>,
+#[serde(rename_all = "camelCase")]
+pub struct TokenBalance<'a> {
+    mint: &'a str,
+
+    #[serde(default)]
+    owner: Option<&'a str>,
+
+    #[serde(borrow)]
+    ui_token_amount: UiTokenAmount<'a>,
+}
+
+#[derive(Deserialize)]
+pub struct UiTokenAmount<'a> {
+    amount: &'a str,
 }
 
+/// Holds `message` with `full` transaction details, `accountKeys` with `accounts`
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct Message {
-    account_keys: Vec<String>,
-}
\ No newline at end of file
+pub struct Transaction<'a> {
+    #[serde(default, borrow)]
+    message: Option<Message<'a>>,
+
+    #[serde(default, borrow)]
+    account_keys: Option<Vec<AccountKey<'a>>>,
+
+    #[serde(borrow)]
+    signatures: Vec<&'a str>,
+}
+
+#[derive(Deserialize)]
+pub struct AccountKey<'a> {
+    pub(super) pubkey: &'a str,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Message<'a> {
+    #[serde(borrow)]
+    account_keys: Vec<&'a str>,
+
+    #[serde(default, borrow)]
+    instructions: Vec<CompiledInstruction<'a>>,
+}
+
+impl<'a> Message<'a> {
+    /// Data of the first Memo program instruction, program ids are always static keys
+    fn memo_data(&self) -> Option<&'a str> {
+        self.instructions
+            .iter()
+            .find(|i| self.account_keys
+                .get(i.program_id_index)
+                .is_some_and(|p| MEMO_PROGRAMS.contains(p)))
+            .map(|i| i.data)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompiledInstruction<'a> {
+    program_id_index: usize,
+    data: &'a str,
+}
+
+/// Memo data is base58 encoded UTF-8
+fn decode_memo(data: &str) -> Option<String> {
+    let b = match bs58::decode(data).into_vec() {
+        Ok(b) => b,
+        Err(e) => {
+            error!("err bs58::decode() in decode_memo(): {}", e);
+            return None;
+        },
+    };
+
+    match String::from_utf8(b) {
+        Ok(memo) => Some(memo),
+        Err(e) => {
+            error!("err String::from_utf8() in decode_memo(): {}", e);
+            None
+        },
+    }
+}
@@ -0,0 +1,178 @@
+use std::{cmp::min, sync::{Arc, Mutex, MutexGuard, PoisonError}, time::Duration};
+
+use anyhow::bail;
+use log::info;
+use tokio::time::Instant;
+
+use super::rate_limit::RateLimiter;
+
+/// Weight of the latest sample in the latency and error rate moving averages
+const EWMA_ALPHA: f64 = 0.2;
+/// Latency penalty added to the score for an endpoint failing every request
+const ERROR_RATE_PENALTY_MS: f64 = 1000.0;
+
+/// Consecutive failures after which an endpoint is degraded
+const DEGRADE_THRESHOLD: u32 = 3;
+const DEGRADE_BASE_DELAY: Duration = Duration::from_secs(10);
+const DEGRADE_MAX_DELAY: Duration = Duration::from_secs(300);
+
+pub struct Endpoint {
+    pub http_url: String,
+    pub ws_url: String,
+    pub priority: u32,
+    pub requests_per_second: Option<u32>,
+    pub burst: Option<u32>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct EndpointId(usize);
+
+#[derive(Default)]
+struct Health {
+    latency_ms: Option<f64>,
+    error_rate: f64,
+    consecutive_failures: u32,
+    degradations: u32,
+    degraded_until: Option<Instant>,
+}
+
+impl Health {
+    /// Lower is better, endpoints without samples are tried first
+    fn score(&self) -> f64 {
+        self.latency_ms.unwrap_or_default() + self.error_rate * ERROR_RATE_PENALTY_MS
+    }
+
+    fn is_degraded(&self, now: Instant) -> bool {
+        self.degraded_until.is_some_and(|until| until > now)
+    }
+}
+
+/// Shared set of RPC endpoints, every request goes to the preferred healthy one.
+/// A degraded endpoint is skipped until its backoff expires and is then tried again.
+#[derive(Clone)]
+pub struct EndpointPool {
+    endpoints: Arc<[Endpoint]>,
+    health: Arc<Mutex<Vec<Health>>>,
+    limiters: Arc<[RateLimiter]>,
+}
+
+impl EndpointPool {
+    pub fn new(endpoints: Vec<Endpoint>) -> anyhow::Result<Self> {
+        if endpoints.is_empty() {
+            bail!("err 'no endpoints configured' in EndpointPool::new()");
+        }
+
+        let health = endpoints.iter()
+            .map(|_| Health::default())
+            .collect();
+
+        let limiters = endpoints.iter()
+            .map(|e| RateLimiter::new(e.requests_per_second, e.burst))
+            .collect();
+
+        Ok(Self { endpoints: endpoints.into(), health: Arc::new(Mutex::new(health)), limiters })
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.endpoints.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.endpoints.is_empty()
+    }
+
+    #[inline]
+    pub fn http_url(&self, id: EndpointId) -> &str {
+        &self.endpoints[id.0].http_url
+    }
+
+    #[inline]
+    pub fn ws_url(&self, id: EndpointId) -> &str {
+        &self.endpoints[id.0].ws_url
+    }
+
+    /// Picks the endpoint with the lowest priority value and the best score among the healthy ones,
+    /// endpoints in `exclude` are only picked if nothing else is left
+    pub fn select(&self, exclude: &[EndpointId]) -> EndpointId {
+        let health = self.health();
+        let now = Instant::now();
+
+        let mut candidates: Vec<_> = (0..self.endpoints.len())
+            .filter(|i| !exclude.contains(&EndpointId(*i)))
+            .collect();
+
+        if candidates.is_empty() {
+            candidates = (0..self.endpoints.len()).collect();
+        }
+
+        let healthy = candidates.iter()
+            .filter(|i| !health[**i].is_degraded(now))
+            .min_by(|a, b| {
+                self.endpoints[**a].priority.cmp(&self.endpoints[**b].priority)
+                    .then(health[**a].score().total_cmp(&health[**b].score()))
+            });
+
+        // Every candidate is degraded, so the one recovering first is the best bet
+        let i = healthy.or_else(|| candidates.iter().min_by_key(|i| health[**i].degraded_until));
+
+        EndpointId(*i.unwrap_or(&0))
+    }
+
+    /// Waits until the rate limit of the endpoint allows `n` more requests
+    #[inline]
+    pub async fn acquire(&self, id: EndpointId, n: u32) {
+        self.limiters[id.0].acquire(n).await
+    }
+
+    /// Holds back every request to the endpoint for `delay`
+    #[inline]
+    pub fn throttle(&self, id: EndpointId, delay: Duration) {
+        self.limiters[id.0].block(delay)
+    }
+
+    pub fn report_success(&self, id: EndpointId, latency: Duration) {
+        let mut health = self.health();
+        let h = &mut health[id.0];
+
+        let latency_ms = latency.as_secs_f64() * 1000.0;
+        h.latency_ms = Some(h.latency_ms.map_or(latency_ms, |l| ewma(l, latency_ms)));
+        h.error_rate = ewma(h.error_rate, 0.0);
+        h.consecutive_failures = 0;
+
+        if h.degradations > 0 {
+            info!("[report_success()] - endpoint {} recovered", id.0);
+
+            h.degradations = 0;
+            h.degraded_until = None;
+        }
+    }
+
+    pub fn report_failure(&self, id: EndpointId) {
+        let mut health = self.health();
+        let h = &mut health[id.0];
+
+        h.error_rate = ewma(h.error_rate, 1.0);
+        h.consecutive_failures += 1;
+
+        if h.consecutive_failures >= DEGRADE_THRESHOLD && !h.is_degraded(Instant::now()) {
+            let delay = DEGRADE_BASE_DELAY.saturating_mul(2u32.saturating_pow(h.degradations));
+            let delay = min(delay, DEGRADE_MAX_DELAY);
+
+            h.degradations += 1;
+            h.degraded_until = Some(Instant::now() + delay);
+
+            info!("[report_failure()] - endpoint {} degraded for {:?}", id.0, delay);
+        }
+    }
+
+    fn health(&self) -> MutexGuard<'_, Vec<Health>> {
+        self.health.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[inline]
+fn ewma(avg: f64, sample: f64) -> f64 {
+    avg + EWMA_ALPHA * (sample - avg)
+}
@@ -0,0 +1,4 @@
+mod endpoint;
+pub use endpoint::*;
+
+pub mod rate_limit;
@@ -0,0 +1,84 @@
+use std::{cmp::max, sync::{Mutex, PoisonError}, time::Duration};
+
+use tokio::time::{sleep, Instant};
+
+/// Client-side token bucket, so requests stay within the quota of the endpoint
+pub struct RateLimiter {
+    /// Tokens added per second, `None` if the endpoint is not limited
+    rate: Option<f64>,
+    capacity: f64,
+    state: Mutex<RateLimiterState>,
+}
+
+struct RateLimiterState {
+    tokens: f64,
+    updated_at: Instant,
+    blocked_until: Option<Instant>,
+}
+
+impl RateLimiter {
+    /// `burst` defaults to `requests_per_second`
+    pub fn new(requests_per_second: Option<u32>, burst: Option<u32>) -> Self {
+        let rate = requests_per_second
+            .filter(|r| *r > 0)
+            .map(f64::from);
+        let capacity = burst.or(requests_per_second)
+            .unwrap_or(1)
+            .max(1)
+            .into();
+
+        let state = RateLimiterState { tokens: capacity, updated_at: Instant::now(), blocked_until: None };
+
+        Self { rate, capacity, state: Mutex::new(state) }
+    }
+
+    /// Waits until `n` requests may be sent, a batch larger than the burst takes the whole bucket
+    pub async fn acquire(&self, n: u32) {
+        while let Some(delay) = self.try_acquire(n, Instant::now()) {
+            sleep(delay).await;
+        }
+    }
+
+    /// Stops all requests for `delay`, used when the endpoint asks to back off
+    pub fn block(&self, delay: Duration) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let until = Instant::now() + delay;
+        let until = state.blocked_until.map_or(until, |u| max(u, until));
+
+        // The bucket starts refilling only once the block is over
+        state.blocked_until = Some(until);
+        state.updated_at = until;
+        state.tokens = 0.0;
+    }
+
+    /// Takes `n` tokens, or returns how long to wait before trying again
+    fn try_acquire(&self, n: u32, now: Instant) -> Option<Duration> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+
+        if let Some(until) = state.blocked_until {
+            if until > now {
+                return Some(until - now);
+            }
+
+            state.blocked_until = None;
+        }
+
+        let rate = self.rate?;
+
+        let elapsed = now.saturating_duration_since(state.updated_at).as_secs_f64();
+        state.tokens = (state.tokens + elapsed * rate).min(self.capacity);
+        state.updated_at = now;
+
+        let n = f64::from(n).min(self.capacity);
+
+        match state.tokens >= n {
+            true => {
+                state.tokens -= n;
+                None
+            },
+
+            false => Some(Duration::from_secs_f64((n - state.tokens) / rate)),
+        }
+    }
+}
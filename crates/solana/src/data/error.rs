@@ -0,0 +1,44 @@
+use std::{error::Error, fmt::{self, Display}, time::Duration};
+
+use hyper::StatusCode;
+
+use super::res::RpcErrorRes;
+
+/// Slot was skipped or is missing due to a ledger jump, so it has no block
+pub const SLOT_SKIPPED: i64 = -32007;
+
+#[derive(Debug)]
+pub enum RpcError {
+    /// HTTP 429, `retry_after` is taken from the `Retry-After` header if the endpoint sent it
+    RateLimited { retry_after: Option<Duration> },
+    /// Any other unsuccessful HTTP status
+    Status(StatusCode),
+    /// The `error` field of a JSON-RPC response
+    Rpc(RpcErrorRes),
+    /// A JSON-RPC response with neither `result` nor `error`
+    MissingResult,
+    /// A result in a batch response which can not be matched to a call
+    MissingId,
+}
+
+impl RpcError {
+    #[inline]
+    pub fn is_slot_skipped(&self) -> bool {
+        matches!(self, Self::Rpc(RpcErrorRes { code: SLOT_SKIPPED, .. }))
+    }
+}
+
+impl Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::RateLimited { retry_after: Some(d) } => write!(f, "rate limited, retry after {:?}", d),
+            Self::RateLimited { retry_after: None } => write!(f, "rate limited"),
+            Self::Status(status) => write!(f, "unexpected status {}", status),
+            Self::Rpc(e) => write!(f, "rpc error {}", e),
+            Self::MissingResult => write!(f, "missing result"),
+            Self::MissingId => write!(f, "missing id"),
+        }
+    }
+}
+
+impl Error for RpcError {}
@@ -1,4 +1,8 @@
+pub mod address;
 pub mod block;
+pub mod endpoint;
+pub mod error;
 pub mod slot;
+pub mod source;
 pub mod req;
 pub mod res;
\ No newline at end of file
@@ -2,12 +2,16 @@ use sonic_rs::Serialize;
 
 use crate::domain::slot::Slot;
 
-use super::slot::res::SubscriptionId;
+use super::{res::RpcId, slot::res::SubscriptionId};
+
+/// Highest transaction version the node may return, `0` enables versioned
+/// transactions with address lookup tables.
+const MAX_SUPPORTED_TRANSACTION_VERSION: u8 = 0;
 
 #[derive(Serialize)]
 pub struct RpcReq<'a, T> {
     jsonrpc: &'a str,
-    id: &'a str,
+    id: RpcId,
     method: &'a str,
 
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -16,7 +20,13 @@ pub struct RpcReq<'a, T> {
 
 impl<'a, T> RpcReq<'a, T> {
     pub fn new(method: &'a str, params: Option<T>) -> Self {
-        Self { jsonrpc: "2.0", id: "1", method, params }
+        Self { jsonrpc: "2.0", id: 1, method, params }
+    }
+
+    /// Sets the id used to match the response in a batch
+    pub fn with_id(mut self, id: RpcId) -> Self {
+        self.id = id;
+        self
     }
 
     pub fn new_slot_subscribe() -> Self {
@@ -25,10 +35,13 @@ impl<'a, T> RpcReq<'a, T> {
 }
 
 impl<'a> RpcReq<'a, [RpcParameter; 2]> {
-    pub fn new_get_block(slot: Slot) -> Self {
+    pub fn new_get_block(slot: Slot, commitment: Commitment, encoding: Encoding, transaction_details: TransactionDetails) -> Self {
         let config = RpcConfig::builder()
-            .with_commitment(Commitment::Finalized)
-            .with_encoding(Encoding::Json);
+            .with_commitment(commitment)
+            .with_encoding(encoding)
+            .with_max_supported_transaction_version(MAX_SUPPORTED_TRANSACTION_VERSION)
+            .with_transaction_details(transaction_details)
+            .with_rewards(false);
 
         Self::new("getBlock", Some([
             RpcParameter::Slot(slot),
@@ -37,12 +50,83 @@ impl<'a> RpcReq<'a, [RpcParameter; 2]> {
     }
 }
 
+impl<'a> RpcReq<'a, [RpcParameter; 2]> {
+    pub fn new_account_subscribe(address: String, commitment: Commitment) -> Self {
+        let config = RpcConfig::builder()
+            .with_commitment(commitment)
+            .with_encoding(Encoding::Base64);
+
+        Self::new("accountSubscribe", Some([
+            RpcParameter::Address(address),
+            RpcParameter::RpcConfig(config),
+        ]))
+    }
+
+    /// Returns signatures newer than `until` and older than `before`, newest first
+    pub fn new_get_signatures_for_address(
+        address: String,
+        before: Option<String>,
+        until: Option<String>,
+        commitment: Commitment,
+    ) -> Self {
+        let config = RpcConfig::builder()
+            .with_commitment(commitment)
+            .with_before(before)
+            .with_until(until);
+
+        Self::new("getSignaturesForAddress", Some([
+            RpcParameter::Address(address),
+            RpcParameter::RpcConfig(config),
+        ]))
+    }
+
+    pub fn new_get_transaction(signature: String, commitment: Commitment, encoding: Encoding) -> Self {
+        let config = RpcConfig::builder()
+            .with_commitment(commitment)
+            .with_encoding(encoding)
+            .with_max_supported_transaction_version(MAX_SUPPORTED_TRANSACTION_VERSION);
+
+        Self::new("getTransaction", Some([
+            RpcParameter::Signature(signature),
+            RpcParameter::RpcConfig(config),
+        ]))
+    }
+}
+
+impl<'a> RpcReq<'a, [RpcParameter; 3]> {
+    pub fn new_get_blocks(start_slot: Slot, end_slot: Slot, commitment: Commitment) -> Self {
+        let config = RpcConfig::builder()
+            .with_commitment(commitment);
+
+        Self::new("getBlocks", Some([
+            RpcParameter::Slot(start_slot),
+            RpcParameter::Slot(end_slot),
+            RpcParameter::RpcConfig(config),
+        ]))
+    }
+}
+
 impl<'a> RpcReq<'a, [RpcParameter; 1]> {
+    pub fn new_get_slot(commitment: Commitment) -> Self {
+        let config = RpcConfig::builder()
+            .with_commitment(commitment);
+
+        Self::new("getSlot", Some([
+            RpcParameter::RpcConfig(config),
+        ]))
+    }
+
     pub fn new_slot_unsubscribe(subscription_id: SubscriptionId) -> Self {
         Self::new("slotUnsubscribe", Some([
             RpcParameter::SubscriptionId(subscription_id),
         ]))
     }
+
+    pub fn new_account_unsubscribe(subscription_id: SubscriptionId) -> Self {
+        Self::new("accountUnsubscribe", Some([
+            RpcParameter::SubscriptionId(subscription_id),
+        ]))
+    }
 }
 
 #[derive(Serialize)]
@@ -51,20 +135,42 @@ pub enum RpcParameter {
     RpcConfig(RpcConfig),
     Slot(Slot),
     SubscriptionId(SubscriptionId),
+    Address(String),
+    Signature(String),
 }
 
 #[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct RpcConfig {
     #[serde(skip_serializing_if = "Option::is_none")]
     commitment: Option<Commitment>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     encoding: Option<Encoding>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_supported_transaction_version: Option<u8>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    before: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    until: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    transaction_details: Option<TransactionDetails>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rewards: Option<bool>,
 }
 
 impl RpcConfig {
     fn builder() -> Self {
-        Self { commitment: None, encoding: None }
+        Self {
+            commitment: None, encoding: None,
+            max_supported_transaction_version: None, before: None, until: None,
+            transaction_details: None, rewards: None,
+        }
     }
 
     fn with_commitment(mut self, commitment: Commitment) -> Self {
@@ -76,16 +182,55 @@ impl RpcConfig {
         self.encoding = Some(encoding);
         self
     }
+
+    fn with_max_supported_transaction_version(mut self, version: u8) -> Self {
+        self.max_supported_transaction_version = Some(version);
+        self
+    }
+
+    fn with_before(mut self, before: Option<String>) -> Self {
+        self.before = before;
+        self
+    }
+
+    fn with_until(mut self, until: Option<String>) -> Self {
+        self.until = until;
+        self
+    }
+
+    fn with_transaction_details(mut self, transaction_details: TransactionDetails) -> Self {
+        self.transaction_details = Some(transaction_details);
+        self
+    }
+
+    fn with_rewards(mut self, rewards: bool) -> Self {
+        self.rewards = Some(rewards);
+        self
+    }
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
 #[serde(rename_all = "camelCase")]
-enum Commitment {
+pub enum Commitment {
+    /// Voted on by a supermajority, the block may still drop off the chain
+    Confirmed,
     Finalized,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum TransactionDetails {
+    /// Whole transactions including instructions and log messages
+    Full,
+    /// Account keys, signatures and balances only, enough for balance diffs
+    Accounts,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
 #[serde(rename_all = "camelCase")]
-enum Encoding {
+pub enum Encoding {
     Json,
+    /// Instructions of known programs are decoded by the node
+    JsonParsed,
+    Base64,
 }
\ No newline at end of file
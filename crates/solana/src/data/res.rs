@@ -1,16 +1,68 @@
+use std::{collections::HashMap, fmt::{self, Display}};
+
+use anyhow::Context;
+use const_format::concatcp;
 use sonic_rs::Deserialize;
 
+use super::error::RpcError;
+
+pub type RpcId = u64;
+
+/// Holds either a result or an error, elements of a batch response are matched by `id`.
+/// `id` is null for errors the node could not tie to a call, such as parse or invalid request errors.
 #[derive(Deserialize)]
 pub struct RpcRes<T> {
-    pub result: T,
+    #[serde(default)]
+    pub id: Option<RpcId>,
+
+    pub result: Option<T>,
+    pub error: Option<RpcErrorRes>,
 }
 
-#[derive(Deserialize)]
-pub struct RpcNotification<T> {
-    pub params: NotificationParams<T>,
+impl<T> RpcRes<T> {
+    pub fn into_result(self) -> Result<T, RpcError> {
+        match (self.result, self.error) {
+            (_, Some(e)) => Err(RpcError::Rpc(e)),
+            (Some(result), None) => Ok(result),
+            (None, None) => Err(RpcError::MissingResult),
+        }
+    }
 }
 
-#[derive(Deserialize)]
-pub struct NotificationParams<T> {
-    pub result: T,
-}
\ No newline at end of file
+/// Keys the responses of a batch by id. A batch the node could not read at all is answered by
+/// a single error, and an error without an id answers no call, so either fails the whole batch.
+pub fn from_batch_slice<'a, T: Deserialize<'a>>(b: &'a [u8]) -> anyhow::Result<HashMap<RpcId, Result<T, RpcError>>> {
+    const FN_CTX: &str = "from_batch_slice()";
+
+    let res: Vec<RpcRes<T>> = match b.trim_ascii_start().first() {
+        Some(b'[') => sonic_rs::from_slice(b)
+            .context(concatcp!("err sonic_rs::from_slice() in ", FN_CTX))?,
+
+        _ => vec![sonic_rs::from_slice(b).context(concatcp!("err sonic_rs::from_slice(single) in ", FN_CTX))?],
+    };
+
+    let mut batch = HashMap::with_capacity(res.len());
+
+    for r in res {
+        let Some(id) = r.id else {
+            let e = r.into_result().err().unwrap_or(RpcError::MissingId);
+            return Err(e).context(concatcp!("err 'response without an id' in ", FN_CTX));
+        };
+
+        batch.insert(id, r.into_result());
+    }
+
+    Ok(batch)
+}
+
+#[derive(Deserialize, Debug)]
+pub struct RpcErrorRes {
+    pub code: i64,
+    pub message: String,
+}
+
+impl Display for RpcErrorRes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.code, self.message)
+    }
+}
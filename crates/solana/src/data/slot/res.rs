@@ -1,10 +1,35 @@
-use sonic_rs::Deserialize;
+use sonic_rs::{Deserialize, Value};
 
-use crate::domain::slot::Slot;
+use crate::{data::res::{RpcErrorRes, RpcId}, domain::slot::Slot};
 
 pub type SubscriptionId = u64;
 
 #[derive(Deserialize)]
 pub struct SlotNotification {
     pub slot: Slot,
-}
\ No newline at end of file
+}
+
+/// Any message of the subscription connection, a response carries `id`, a notification carries `params`
+#[derive(Deserialize)]
+pub struct WsMessage {
+    #[serde(default)]
+    pub id: Option<RpcId>,
+
+    #[serde(default)]
+    pub method: Option<String>,
+
+    #[serde(default)]
+    pub result: Option<Value>,
+
+    #[serde(default)]
+    pub error: Option<RpcErrorRes>,
+
+    #[serde(default)]
+    pub params: Option<WsParams>,
+}
+
+#[derive(Deserialize)]
+pub struct WsParams {
+    pub subscription: SubscriptionId,
+    pub result: Value,
+}
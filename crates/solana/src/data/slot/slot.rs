@@ -1,35 +1,92 @@
-use anyhow::Context;
+use std::{cmp::min, collections::HashMap, future::pending, time::Duration};
+
+use anyhow::{bail, Context};
+use app::domain::address::AddressEvent;
 use const_format::concatcp;
-use fastwebsockets::{FragmentCollector, Frame, Payload};
+use fastwebsockets::{FragmentCollector, Frame, OpCode, Payload};
 use hyper::upgrade::Upgraded;
 use hyper_util::rt::TokioIo;
-use log::error;
-use tokio::{select, sync::mpsc::{channel, Receiver, Sender, UnboundedSender}, task};
+use log::{error, info};
+use sonic_rs::Serialize;
+use tokio::{select, sync::mpsc::{channel, error::TryRecvError, Receiver, Sender, UnboundedReceiver, UnboundedSender}, task, time::{sleep, timeout, Instant}};
 use tokio_util::sync::CancellationToken;
 
-use crate::{data::{req::RpcReq, res::{RpcNotification, RpcRes}}, domain::slot::{Slot, SlotTx}};
+use crate::{data::{address::watch::AddressWatch, endpoint::{EndpointId, EndpointPool}, req::{Commitment, RpcReq}, res::RpcId}, domain::slot::{Slot, SlotTx}, service::parser::to_address};
 
-use super::res::{SlotNotification, SubscriptionId};
+use super::res::{SlotNotification, SubscriptionId, WsMessage};
 
 type Fc = FragmentCollector<TokioIo<Upgraded>>;
 
+/// A ping is sent if nothing is received for this long
+const PING_INTERVAL: Duration = Duration::from_secs(10);
+/// The connection is considered dead if nothing, including a pong, is received for this long
+const DEAD_CONNECTION_TIMEOUT: Duration = Duration::from_secs(30);
+
+const RECONNECT_BASE_DELAY: Duration = Duration::from_secs(1);
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(30);
+
+const ACCOUNT_NOTIFICATION: &str = "accountNotification";
+
 pub struct SlotActor {
     slot_rx: Receiver<SlotTx>,
-    initial_slot: Option<Slot>,
-    fc: Fc,
+    endpoints: EndpointPool,
+    endpoint: Option<EndpointId>,
+    fc: Option<Fc>,
+    last_slot: Option<Slot>,
+    reconnect_attempts: u32,
+    next_id: RpcId,
+    accounts: Option<AccountSubscriptions>,
+}
+
+/// `accountSubscribe` subscriptions of pending payment addresses, multiplexed over the slot connection
+struct AccountSubscriptions {
+    address_rx: UnboundedReceiver<AddressEvent>,
+    watch: AddressWatch,
+    commitment: Commitment,
+    /// Subscribe requests waiting for their response
+    requests: HashMap<RpcId, String>,
+    subscriptions: HashMap<SubscriptionId, String>,
 }
 
 impl SlotActor {
-    pub fn new(fc: Fc, initial_slot: Option<Slot>) -> (Self, Sender<SlotTx>) {
+    pub fn new(endpoints: EndpointPool) -> (Self, Sender<SlotTx>) {
         let (tx, slot_rx) = channel(1);
-        (Self { slot_rx, initial_slot, fc }, tx)
+
+        (
+            Self {
+                slot_rx, endpoints,
+                endpoint: None, fc: None,
+                last_slot: None, reconnect_attempts: 0,
+                next_id: 1, accounts: None,
+            },
+            tx,
+        )
+    }
+
+    /// Subscribes to every address published on `address_rx` and marks it in `watch` whenever its lamports change
+    pub fn with_accounts(
+        mut self,
+        address_rx: UnboundedReceiver<AddressEvent>,
+        watch: AddressWatch,
+        commitment: Commitment,
+    ) -> Self {
+        self.accounts = Some(AccountSubscriptions {
+            address_rx, watch, commitment,
+            requests: HashMap::new(),
+            subscriptions: HashMap::new(),
+        });
+        self
     }
 
     pub async fn start(mut self, token: CancellationToken) {
+        const FN_CTX: &str = "SlotActor::start()";
+
         loop {
             select! {
-                Some(tx) = self.slot_rx.recv() => if let Err(e) = self.serve_slot_subscription(tx).await {
-                    error!("err self.serve_slot_subscription() in SlotActor::start(): {:#?}", e);
+                Some(tx) = self.slot_rx.recv() => self.serve_slot_subscription(tx, &token).await,
+
+                Some(event) = next_address_event(&mut self.accounts) => if let Err(e) = self.process_address_event(event).await {
+                    error!("err self.process_address_event() in {}: {:#?}", FN_CTX, e);
                 },
 
                 _ = token.cancelled() => return,
@@ -39,76 +96,358 @@ impl SlotActor {
         }
     }
 
-    async fn slot_subscribe(&mut self) -> anyhow::Result<Slot> {
-        const FN_CTX: &str = "slot_subscribe()";
+    fn fc(&mut self) -> anyhow::Result<&mut Fc> {
+        match &mut self.fc {
+            Some(fc) => Ok(fc),
+            None => bail!("err 'not connected' in fc()"),
+        }
+    }
+
+    async fn send<T: Serialize>(&mut self, req: RpcReq<'_, T>) -> anyhow::Result<RpcId> {
+        const FN_CTX: &str = "send()";
 
-        let req: RpcReq<()> = RpcReq::new_slot_subscribe();
-        let payload = sonic_rs::to_vec(&req)
-            .context(concatcp!("err sonic_rs::to_vec() in ", FN_CTX))?;
+        let id = self.next_id;
+        self.next_id += 1;
 
-        self.fc.write_frame(Frame::text(Payload::Borrowed(&payload)))
-            .await
-            .context(concatcp!("err fc.write_frame() in", FN_CTX))?;
+        let payload = sonic_rs::to_vec(&req.with_id(id))
+            .context(concatcp!("err sonic_rs::to_vec() in ", FN_CTX))?;
 
-        let frame = self.fc.read_frame()
+        self.fc()?
+            .write_frame(Frame::text(Payload::Borrowed(&payload)))
             .await
-            .context(concatcp!("err fc.read_frame() in", FN_CTX))?;
-        let res: RpcRes<Slot> = sonic_rs::from_slice(&frame.payload)
-            .context(concatcp!("err sonic_rs::from_slice() in", FN_CTX))?;
+            .context(concatcp!("err fc.write_frame() in ", FN_CTX))?;
 
-        Ok(res.result)
+        Ok(id)
     }
 
-    async fn slot_unsubscribe(&mut self, subscription_id: SubscriptionId) -> anyhow::Result<()> {
-        const FN_CTX: &str = "slot_unsubscribe()";
+    /// Keeps the subscription alive until `tx` is closed, reconnecting with backoff whenever the connection drops
+    async fn serve_slot_subscription(&mut self, tx: UnboundedSender<Slot>, token: &CancellationToken) {
+        const FN_CTX: &str = "serve_slot_subscription()";
 
-        let req = RpcReq::new_slot_unsubscribe(subscription_id);
-        let payload = sonic_rs::to_vec(&req)
-            .context(concatcp!("err sonic_rs::to_vec() in ", FN_CTX))?;
+        while !tx.is_closed() {
+            let e = match self.serve_connection(&tx).await {
+                Ok(()) => return,
+                Err(e) => e,
+            };
 
-        self.fc.write_frame(Frame::text(Payload::Borrowed(&payload)))
-            .await
-            .context(concatcp!("err fc.write_frame() in ", FN_CTX))?;
+            self.fc = None;
+            self.reconnect_attempts += 1;
 
-        Ok(())
+            if let Some(endpoint) = self.endpoint {
+                self.endpoints.report_failure(endpoint);
+            }
+
+            let delay = RECONNECT_BASE_DELAY.saturating_mul(2u32.saturating_pow(self.reconnect_attempts - 1));
+            let delay = min(delay, RECONNECT_MAX_DELAY);
+
+            error!("err self.serve_connection() in {}, reconnecting in {:?}: {:#?}", FN_CTX, delay, e);
+
+            select! {
+                _ = sleep(delay) => continue,
+
+                _ = token.cancelled() => return,
+            }
+        }
     }
 
-    async fn serve_slot_subscription(&mut self, tx: UnboundedSender<Slot>) -> anyhow::Result<()> {
-        const FN_CTX: &str = "serve_slot_subscription()";
+    async fn serve_connection(&mut self, tx: &UnboundedSender<Slot>) -> anyhow::Result<()> {
+        const FN_CTX: &str = "serve_connection()";
+
+        if self.fc.is_none() {
+            // The endpoint which just failed is only picked again if it is the last one left
+            let exclude: Vec<_> = self.endpoint.into_iter().collect();
+            let endpoint = self.endpoints.select(&exclude);
+            self.endpoint = Some(endpoint);
 
-        let subscription_id = self.slot_subscribe()
+            let fc = fastwebsocketslib::connect(self.endpoints.ws_url(endpoint))
+                .await
+                .context(concatcp!("err fastwebsocketslib::connect() in ", FN_CTX))?;
+
+            self.fc = Some(fc);
+
+            self.resubscribe_accounts()
+                .await
+                .context(concatcp!("err self.resubscribe_accounts() in ", FN_CTX))?;
+        }
+
+        let slot_request = self.send(RpcReq::<()>::new_slot_subscribe())
             .await
-            .context(concatcp!("err self.slot_subscribe() in ", FN_CTX))?;
+            .context(concatcp!("err self.send(slotSubscribe) in ", FN_CTX))?;
+
+        let mut slot_subscription = None;
+
+        let mut last_frame_at = Instant::now();
+        let mut ping_sent_at = None;
 
         loop {
             if tx.is_closed() {
-                self.slot_unsubscribe(subscription_id)
-                    .await
-                    .context(concatcp!("err self.slot_unsubscribe in ", FN_CTX))?;
+                match slot_subscription {
+                    Some(id) => {
+                        self.send(RpcReq::new_slot_unsubscribe(id))
+                            .await
+                            .context(concatcp!("err self.send(slotUnsubscribe) in ", FN_CTX))?;
+                    },
 
-                break;
+                    // The subscription may still be created, dropping the connection is the only way to cancel it
+                    None => self.fc = None,
+                }
+
+                return Ok(());
             }
 
-            let frame = self.fc.read_frame()
+            // `read_frame` is not cancel safe, so address events are only handled between frames
+            self.process_address_events()
                 .await
-                .context(concatcp!("err fc.read_frame() in ", FN_CTX))?;
-            let notification: RpcNotification<SlotNotification> = sonic_rs::from_slice(&frame.payload)
+                .context(concatcp!("err self.process_address_events() in ", FN_CTX))?;
+
+            let fc = self.fc()?;
+
+            let frame = match timeout(PING_INTERVAL, fc.read_frame()).await {
+                Ok(frame) => frame.context(concatcp!("err fc.read_frame() in ", FN_CTX))?,
+
+                Err(_) => {
+                    if last_frame_at.elapsed() >= DEAD_CONNECTION_TIMEOUT {
+                        bail!("err 'no frames received for {:?}' in {}", DEAD_CONNECTION_TIMEOUT, FN_CTX);
+                    }
+
+                    fc.write_frame(Frame::new(true, OpCode::Ping, None, Payload::Borrowed(&[])))
+                        .await
+                        .context(concatcp!("err fc.write_frame(ping) in ", FN_CTX))?;
+
+                    ping_sent_at = Some(Instant::now());
+
+                    continue;
+                },
+            };
+
+            last_frame_at = Instant::now();
+
+            match frame.opcode {
+                OpCode::Text | OpCode::Binary => (),
+                OpCode::Close => bail!("err 'connection closed by the server' in {}", FN_CTX),
+
+                // The round trip of a ping is the only latency sample a subscription provides
+                OpCode::Pong => {
+                    if let (Some(sent_at), Some(endpoint)) = (ping_sent_at.take(), self.endpoint) {
+                        self.endpoints.report_success(endpoint, sent_at.elapsed());
+                    }
+
+                    continue;
+                },
+
+                _ => continue,
+            }
+
+            let msg: WsMessage = sonic_rs::from_slice(&frame.payload)
                 .context(concatcp!("err sonic_rs::from_slice() in ", FN_CTX))?;
 
-            let slot = notification.params.result.slot;
+            if let Some(params) = msg.params {
+                if slot_subscription == Some(params.subscription) {
+                    let notification: SlotNotification = sonic_rs::from_value(&params.result)
+                        .context(concatcp!("err sonic_rs::from_value() in ", FN_CTX))?;
+
+                    self.forward_slot(tx, notification.slot)
+                        .context(concatcp!("err self.forward_slot() in ", FN_CTX))?;
+                } else if msg.method.as_deref() == Some(ACCOUNT_NOTIFICATION) {
+                    self.process_account_notification(params.subscription);
+                }
+
+                continue;
+            }
+
+            let Some(id) = msg.id else {
+                continue;
+            };
+
+            if id == slot_request {
+                if let Some(e) = msg.error {
+                    bail!("err 'slotSubscribe failed: {}' in {}", e, FN_CTX);
+                }
+
+                let id = msg.result
+                    .map(|r| sonic_rs::from_value(&r))
+                    .transpose()
+                    .context(concatcp!("err sonic_rs::from_value() in ", FN_CTX))?;
 
-            if let Some(initial_slot) = self.initial_slot {
-                for slot in initial_slot..slot {
-                    tx.send(slot)
-                        .context(concatcp!("err tx.send(initial_slot) in ", FN_CTX))?;
+                slot_subscription = id;
+
+                if self.reconnect_attempts > 0 {
+                    info!("[{}] - resubscribed, last sent slot: {:?}", FN_CTX, self.last_slot);
+                    self.reconnect_attempts = 0;
                 }
-                self.initial_slot = None;
+
+                continue;
             }
 
-            tx.send(slot)
-                .context(concatcp!("err tx.send(slot) in ", FN_CTX))?;
+            self.process_account_response(id, msg)
+                .await
+                .context(concatcp!("err self.process_account_response() in ", FN_CTX))?;
+        }
+    }
+
+    fn forward_slot(&mut self, tx: &UnboundedSender<Slot>, slot: Slot) -> anyhow::Result<()> {
+        // After a reconnect the node may repeat slots which were already sent downstream
+        if self.last_slot.is_some_and(|s| slot <= s) {
+            return Ok(());
+        }
+
+        self.last_slot = Some(slot);
+
+        tx.send(slot)
+            .context("err tx.send(slot) in forward_slot()")?;
+
+        Ok(())
+    }
+
+    /// Subscriptions do not outlive the connection, so every watched address is subscribed again
+    /// and looked up in case a change was missed in between
+    async fn resubscribe_accounts(&mut self) -> anyhow::Result<()> {
+        let Some(accounts) = &mut self.accounts else {
+            return Ok(());
+        };
+
+        accounts.requests.clear();
+        accounts.subscriptions.clear();
+        accounts.watch.mark_all_changed();
+
+        for address in accounts.watch.addresses() {
+            self.account_subscribe(address)
+                .await
+                .context("err self.account_subscribe() in resubscribe_accounts()")?;
+        }
+
+        Ok(())
+    }
+
+    async fn process_address_events(&mut self) -> anyhow::Result<()> {
+        while let Some(accounts) = &mut self.accounts {
+            let event = match accounts.address_rx.try_recv() {
+                Ok(event) => event,
+                Err(TryRecvError::Empty | TryRecvError::Disconnected) => return Ok(()),
+            };
+
+            self.process_address_event(event)
+                .await
+                .context("err self.process_address_event() in process_address_events()")?;
         }
 
         Ok(())
     }
-}
\ No newline at end of file
+
+    async fn process_address_event(&mut self, event: AddressEvent) -> anyhow::Result<()> {
+        const FN_CTX: &str = "process_address_event()";
+
+        let Some(accounts) = &mut self.accounts else {
+            return Ok(());
+        };
+
+        match event {
+            AddressEvent::Pending(pubkey) => {
+                let Some(address) = to_address(&pubkey) else {
+                    return Ok(());
+                };
+
+                if accounts.watch.watch(address.clone()) && self.fc.is_some() {
+                    self.account_subscribe(address)
+                        .await
+                        .context(concatcp!("err self.account_subscribe() in ", FN_CTX))?;
+                }
+            },
+
+            AddressEvent::Completed(pubkey) => {
+                let Some(address) = to_address(&pubkey) else {
+                    return Ok(());
+                };
+
+                accounts.watch.unwatch(&address);
+
+                let subscription = accounts.subscriptions.iter()
+                    .find(|(_, a)| **a == address)
+                    .map(|(id, _)| *id);
+
+                // A subscription still being created is cancelled once its response arrives
+                if let Some(id) = subscription {
+                    accounts.subscriptions.remove(&id);
+
+                    if self.fc.is_some() {
+                        self.send(RpcReq::new_account_unsubscribe(id))
+                            .await
+                            .context(concatcp!("err self.send(accountUnsubscribe) in ", FN_CTX))?;
+                    }
+                }
+            },
+        }
+
+        Ok(())
+    }
+
+    async fn account_subscribe(&mut self, address: String) -> anyhow::Result<()> {
+        let Some(commitment) = self.accounts.as_ref().map(|a| a.commitment) else {
+            return Ok(());
+        };
+
+        let id = self.send(RpcReq::new_account_subscribe(address.clone(), commitment))
+            .await
+            .context("err self.send(accountSubscribe) in account_subscribe()")?;
+
+        if let Some(accounts) = &mut self.accounts {
+            accounts.requests.insert(id, address);
+        }
+
+        Ok(())
+    }
+
+    async fn process_account_response(&mut self, id: RpcId, msg: WsMessage) -> anyhow::Result<()> {
+        const FN_CTX: &str = "process_account_response()";
+
+        // Unsubscribe responses are not tracked
+        let Some(accounts) = &mut self.accounts else {
+            return Ok(());
+        };
+        let Some(address) = accounts.requests.remove(&id) else {
+            return Ok(());
+        };
+
+        if let Some(e) = msg.error {
+            // The address is still looked up whenever another change brings it up
+            error!("err 'accountSubscribe failed' in {}, address: {}: {}", FN_CTX, address, e);
+            return Ok(());
+        }
+
+        let subscription: SubscriptionId = msg.result
+            .map(|r| sonic_rs::from_value(&r))
+            .transpose()
+            .context(concatcp!("err sonic_rs::from_value() in ", FN_CTX))?
+            .context(concatcp!("err 'missing result' in ", FN_CTX))?;
+
+        if !accounts.watch.is_watched(&address) {
+            self.send(RpcReq::new_account_unsubscribe(subscription))
+                .await
+                .context(concatcp!("err self.send(accountUnsubscribe) in ", FN_CTX))?;
+
+            return Ok(());
+        }
+
+        accounts.subscriptions.insert(subscription, address);
+
+        Ok(())
+    }
+
+    /// The notification only carries the new account state, the transfer itself is looked up by `AddressRepo`
+    fn process_account_notification(&mut self, subscription: SubscriptionId) {
+        let Some(accounts) = &self.accounts else {
+            return;
+        };
+
+        if let Some(address) = accounts.subscriptions.get(&subscription) {
+            accounts.watch.mark_changed(address);
+        }
+    }
+}
+
+async fn next_address_event(accounts: &mut Option<AccountSubscriptions>) -> Option<AddressEvent> {
+    match accounts {
+        Some(accounts) => accounts.address_rx.recv().await,
+        None => pending().await,
+    }
+}
@@ -0,0 +1,43 @@
+use app::domain::transfer::IncomingTransfer;
+
+use crate::domain::slot::Slot;
+
+use super::{address::AddressRepo, block::{BlockRepo, BlockService}, req::Commitment};
+
+/// Detection strategy picked at startup
+pub enum BlockSource {
+    /// Scans every block
+    Blocks(BlockService),
+    /// Looks up only the addresses of pending payments
+    Addresses(AddressRepo),
+}
+
+impl BlockRepo for BlockSource {
+    async fn get_block(&self, slot: Slot, commitment: Commitment) -> anyhow::Result<Vec<IncomingTransfer>> {
+        match self {
+            Self::Blocks(s) => s.get_block(slot, commitment).await,
+            Self::Addresses(s) => s.get_block(slot, commitment).await,
+        }
+    }
+
+    async fn get_block_batch(&self, slots: &[Slot], commitment: Commitment) -> anyhow::Result<Vec<anyhow::Result<Vec<IncomingTransfer>>>> {
+        match self {
+            Self::Blocks(s) => s.get_block_batch(slots, commitment).await,
+            Self::Addresses(s) => s.get_block_batch(slots, commitment).await,
+        }
+    }
+
+    async fn get_blocks(&self, start_slot: Slot, end_slot: Slot, commitment: Commitment) -> anyhow::Result<Vec<Slot>> {
+        match self {
+            Self::Blocks(s) => s.get_blocks(start_slot, end_slot, commitment).await,
+            Self::Addresses(s) => s.get_blocks(start_slot, end_slot, commitment).await,
+        }
+    }
+
+    async fn get_slot(&self, commitment: Commitment) -> anyhow::Result<Slot> {
+        match self {
+            Self::Blocks(s) => s.get_slot(commitment).await,
+            Self::Addresses(s) => s.get_slot(commitment).await,
+        }
+    }
+}
@@ -3,4 +3,7 @@ use tokio::sync::mpsc::UnboundedSender;
 pub type Slot = u64;
 pub type SlotTx = UnboundedSender<Slot>;
 
-pub const SLOT_CONFIRMATION_LAG: Slot = 40;
\ No newline at end of file
+pub const SLOT_CONFIRMATION_LAG: Slot = 40;
+
+/// Widest range accepted by a single `getBlocks` call
+pub const MAX_BLOCKS_RANGE: Slot = 500_000;
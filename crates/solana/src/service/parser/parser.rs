@@ -17,4 +17,12 @@ pub fn to_pubkey(address: &String) -> anyhow::Result<Pubkey> {
         .context("err bs58::decode() in to_pubkey()")?;
 
     Ok(Pubkey::Ed25519(pk))
-}
\ No newline at end of file
+}
+
+/// Only ed25519 keys are Solana addresses
+pub fn to_address(pubkey: &Pubkey) -> Option<String> {
+    match pubkey {
+        Pubkey::Ed25519(pk) => Some(bs58::encode(pk).into_string()),
+        Pubkey::Secp256k1(_) => None,
+    }
+}
@@ -1,18 +1,26 @@
-use app::domain::transfer::{IncomingTransfer, IncomingTransferParsed, TransferDataParsed};
+use app::domain::{height::Height, transfer::{IncomingTransfer, IncomingTransferParsed, TransferDataParsed}};
 
 use crate::service::parser::to_pubkey;
 
 pub trait TryIntoParsed {
-    fn try_into_parsed(self) -> anyhow::Result<IncomingTransferParsed>;
+    fn try_into_parsed(self, height: Height) -> anyhow::Result<IncomingTransferParsed>;
 }
 
 impl TryIntoParsed for IncomingTransfer {
-    fn try_into_parsed(self) -> anyhow::Result<IncomingTransferParsed> {
-        let (transfer_data, signatures) = self.expose();
+    fn try_into_parsed(mut self, height: Height) -> anyhow::Result<IncomingTransferParsed> {
+        let block_time = self.block_time();
+        let memo = self.take_memo();
+        let account_keys = self.take_account_keys();
+        let (mut transfer_data, signatures) = self.expose();
         let amount = transfer_data.amount();
+        let mint = transfer_data.take_mint();
+        let source = transfer_data.take_source();
         let pubkey = to_pubkey(&transfer_data.address())?;
 
-        let transfer_data = TransferDataParsed::new(pubkey, amount);
-        Ok(IncomingTransferParsed::new(transfer_data, signatures))
+        let transfer_data = TransferDataParsed::new(pubkey, amount, mint).with_source(source);
+        Ok(IncomingTransferParsed::new(transfer_data, signatures, height)
+            .with_block_time(block_time)
+            .with_memo(memo)
+            .with_account_keys(account_keys))
     }
 }
\ No newline at end of file
@@ -1,4 +1,6 @@
 pub mod mappers;
+pub mod retries;
+pub mod sequencer;
 
 mod service;
 pub use service::*;
\ No newline at end of file
@@ -0,0 +1,77 @@
+use std::{cmp::min, collections::BTreeMap, time::Duration};
+
+use tokio::time::Instant;
+
+use crate::domain::slot::Slot;
+
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(300);
+
+/// Slots whose blocks failed to fetch or parse, retried with exponential backoff
+pub struct RetryQueue {
+    slots: BTreeMap<Slot, RetryState>,
+}
+
+struct RetryState {
+    attempts: u32,
+    retry_at: Instant,
+}
+
+impl RetryQueue {
+    /// Slots restored from the database are retried right away
+    pub fn new(slots: Vec<Slot>) -> Self {
+        let now = Instant::now();
+        let slots = slots.into_iter()
+            .map(|s| (s, RetryState { attempts: 0, retry_at: now }))
+            .collect();
+
+        Self { slots }
+    }
+
+    pub fn push(&mut self, slot: Slot) {
+        let retry_at = Instant::now() + RETRY_BASE_DELAY;
+        self.slots.insert(slot, RetryState { attempts: 0, retry_at });
+    }
+
+    /// Returns slots which are ready to retry in slot order
+    pub fn due(&self, now: Instant) -> Vec<Slot> {
+        self.slots.iter()
+            .filter(|(_, state)| state.retry_at <= now)
+            .map(|(slot, _)| *slot)
+            .collect()
+    }
+
+    /// Postpones the slot after a failed attempt and returns the number of attempts made
+    pub fn backoff(&mut self, slot: Slot) -> u32 {
+        let Some(state) = self.slots.get_mut(&slot) else {
+            return 0;
+        };
+
+        state.attempts += 1;
+
+        let delay = RETRY_BASE_DELAY.saturating_mul(2u32.saturating_pow(state.attempts));
+        state.retry_at = Instant::now() + min(delay, RETRY_MAX_DELAY);
+
+        state.attempts
+    }
+
+    #[inline]
+    pub fn remove(&mut self, slot: Slot) {
+        self.slots.remove(&slot);
+    }
+
+    #[inline]
+    pub fn contains(&self, slot: Slot) -> bool {
+        self.slots.contains_key(&slot)
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+}
@@ -0,0 +1,35 @@
+use std::cmp::min;
+
+use crate::domain::slot::{Slot, MAX_BLOCKS_RANGE};
+
+/// Tracks the next slot to process, so every confirmed slot is visited exactly once
+/// regardless of which notifications the subscription delivers.
+pub struct Sequencer {
+    next_slot: Option<Slot>,
+}
+
+impl Sequencer {
+    pub fn new(height: Option<Slot>) -> Self {
+        Self { next_slot: height.map(|h| h + 1) }
+    }
+
+    /// Returns the inclusive range of slots up to `end_slot` which are not processed yet
+    pub fn next_range(&mut self, end_slot: Slot) -> Option<(Slot, Slot)> {
+        let start_slot = *self.next_slot.get_or_insert(end_slot);
+
+        match start_slot <= end_slot {
+            true => Some((start_slot, min(end_slot, start_slot + MAX_BLOCKS_RANGE - 1))),
+            false => None,
+        }
+    }
+
+    #[inline]
+    pub fn next_slot(&self) -> Option<Slot> {
+        self.next_slot
+    }
+
+    #[inline]
+    pub fn commit(&mut self, slot: Slot) {
+        self.next_slot = Some(slot + 1);
+    }
+}
@@ -1,48 +1,121 @@
-use anyhow::Context;
-use app::domain::{height::Height, state::State, transfer::IncomingTransferParsed};
+use std::{cmp::min, collections::BTreeSet, time::Duration};
+
+use anyhow::{anyhow, Context};
+use app::domain::{height::{Height, HeightMsg, HeightTx}, pending::PendingRx, transfer::{IncomingTransfer, IncomingTransferParsed, TransferEvent, TransferTx}};
 use const_format::concatcp;
+use futures::{stream, StreamExt};
 use lazy_channel::mpsc::receiver::LazyUnboundedReceiver;
-use log::{debug, error};
-use tokio::{select, sync::mpsc::{unbounded_channel, Sender, UnboundedReceiver, UnboundedSender}, task};
+use log::{debug, error, info};
+use metrics::{counter, gauge};
+use tokio::{select, sync::mpsc::{unbounded_channel, Sender}, task, time::{interval, Instant, MissedTickBehavior}};
 use tokio_util::sync::CancellationToken;
 
-use crate::{data::block::BlockRepo, domain::slot::{Slot, SlotTx, SLOT_CONFIRMATION_LAG}};
+use crate::{data::{block::BlockRepo, req::Commitment}, domain::slot::{Slot, SlotTx, SLOT_CONFIRMATION_LAG}};
+
+use super::{mappers::TryIntoParsed, retries::RetryQueue, sequencer::Sequencer};
 
-use super::mappers::TryIntoParsed;
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
 
 pub struct TransfersServiceActor<B> {
-    state_rx: UnboundedReceiver<State>,
+    pending_rx: PendingRx,
     slot_tx: Sender<SlotTx>,
     slot_rx: LazyUnboundedReceiver<Slot>,
-    transfers_tx: UnboundedSender<IncomingTransferParsed>,
-    height_tx: UnboundedSender<Height>,
+    transfers_tx: TransferTx,
+    height_tx: HeightTx,
     block_repo: B,
+    sequencer: Sequencer,
+    retries: RetryQueue,
+    concurrency: usize,
+    batch_size: usize,
+    commitment: Commitment,
+    confirmation_lag: Slot,
+    /// Next slot to check against the finalized chain, used below `Commitment::Finalized` only
+    finality: Sequencer,
+    /// Processed blocks which are not finalized yet
+    unfinalized: BTreeSet<Slot>,
 }
 
 impl<B: BlockRepo> TransfersServiceActor<B> {
     pub fn new(
-        state_rx: UnboundedReceiver<State>,
+        pending_rx: PendingRx,
         slot_tx: Sender<SlotTx>,
-        transfers_tx: UnboundedSender<IncomingTransferParsed>,
-        height_tx: UnboundedSender<Height>,
+        transfers_tx: TransferTx,
+        height_tx: HeightTx,
         block_repo: B,
+        height: Option<Height>,
     ) -> Self {
-        Self { state_rx, slot_tx, transfers_tx, height_tx, block_repo, slot_rx: Default::default() }
+        Self {
+            pending_rx, slot_tx,
+            transfers_tx, height_tx,
+            block_repo,
+            slot_rx: Default::default(),
+            sequencer: Sequencer::new(height),
+            retries: RetryQueue::new(Vec::new()),
+            concurrency: 1,
+            batch_size: 1,
+            commitment: Commitment::Finalized,
+            confirmation_lag: SLOT_CONFIRMATION_LAG,
+            finality: Sequencer::new(height),
+            unfinalized: BTreeSet::new(),
+        }
+    }
+
+    /// Restores slots which failed to process before the restart
+    pub fn with_retries(mut self, retries: Vec<Height>) -> Self {
+        self.retries = RetryQueue::new(retries);
+        self
+    }
+
+    /// Sets the number of requests sent at once
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Sets the number of `getBlock` calls sent in a single JSON-RPC batch
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Sets the commitment blocks are processed at, below `Commitment::Finalized`
+    /// transfers from blocks dropping off the chain are reverted
+    pub fn with_commitment(mut self, commitment: Commitment) -> Self {
+        self.commitment = commitment;
+        self
+    }
+
+    /// Sets the number of slots the processed range trails the notified slot by
+    pub fn with_confirmation_lag(mut self, confirmation_lag: Slot) -> Self {
+        self.confirmation_lag = confirmation_lag;
+        self
     }
 
     pub async fn start(mut self, token: CancellationToken) {
         const FN_CTX: &str = "TransferServiceActor::start()";
 
+        let mut retry_interval = interval(RETRY_INTERVAL);
+        retry_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        // Payments restored on startup may be counted before the actor runs
+        if let Err(e) = self.process_pending().await {
+            error!("err self.process_pending() in {}: {:#?}", FN_CTX, e);
+        }
+
         loop {
             select! {
-                Some(state) = self.state_rx.recv() => if let Err(e) = self.process_state(state).await {
-                    error!("err self.process_state() in {}: {:#?}", FN_CTX, e);
+                Ok(()) = self.pending_rx.changed() => if let Err(e) = self.process_pending().await {
+                    error!("err self.process_pending() in {}: {:#?}", FN_CTX, e);
                 },
 
                 Some(slot) = self.slot_rx.recv() => if let Err(e) = self.process_slot(slot).await {
                     error!("err self.process_slot() in {}: {:#?}", FN_CTX, e);
                 },
 
+                _ = retry_interval.tick() => if let Err(e) = self.process_retries().await {
+                    error!("err self.process_retries() in {}: {:#?}", FN_CTX, e);
+                },
+
                 _ = token.cancelled() => return,
 
                 _ = task::yield_now() => continue,
@@ -50,19 +123,22 @@ impl<B: BlockRepo> TransfersServiceActor<B> {
         }
     }
 
-    async fn process_state(&mut self, state: State) -> anyhow::Result<()> {
-        match state {
-            State::Running if self.slot_rx.is_closed() => {
+    /// Follows the latest pending count only, so quick changes in between can not leave it out of sync
+    async fn process_pending(&mut self) -> anyhow::Result<()> {
+        let pending = *self.pending_rx.borrow_and_update();
+
+        match pending {
+            0 => self.slot_rx.close(),
+
+            _ if self.slot_rx.is_closed() => {
                 let (tx, rx) = unbounded_channel();
 
                 self.slot_rx.init(rx);
                 self.slot_tx.send(tx)
                     .await
-                    .context("err slot_tx.send() in process_state()")?;
+                    .context("err slot_tx.send() in process_pending()")?;
             },
 
-            State::Stopping => self.slot_rx.close(),
-
             _ => (),
         }
 
@@ -74,21 +150,267 @@ impl<B: BlockRepo> TransfersServiceActor<B> {
 
         debug!("[{}] - new slot: {}", FN_CTX, slot);
 
-        let transfers = self.block_repo.get_block(slot.saturating_sub(SLOT_CONFIRMATION_LAG))
+        let commitment_slot = self.block_repo.get_slot(self.commitment)
+            .await
+            .context(concatcp!("err block_repo.get_slot() in ", FN_CTX))?;
+
+        // `getBlocks` silently omits slots above the commitment, they must not be taken as skipped
+        let end_slot = min(slot.saturating_sub(self.confirmation_lag), commitment_slot);
+
+        let Some((start_slot, end_slot)) = self.sequencer.next_range(end_slot) else {
+            return Ok(());
+        };
+
+        // The chain is checked from the first processed slot on
+        if self.finality.next_slot().is_none() {
+            self.finality = Sequencer::new(start_slot.checked_sub(1));
+        }
+
+        let blocks = self.block_repo.get_blocks(start_slot, end_slot, self.commitment)
+            .await
+            .context(concatcp!("err block_repo.get_blocks() in ", FN_CTX))?;
+
+        let block_repo = &self.block_repo;
+        let commitment = self.commitment;
+
+        // `buffered` yields batches in slot order, so transfers and the height stay ordered
+        // while up to `concurrency` batches are fetched at once
+        let batches: Vec<_> = blocks.chunks(self.batch_size)
+            .map(|batch| batch.to_vec())
+            .collect();
+
+        let mut batches = stream::iter(batches)
+            .map(|batch| fetch_batch(block_repo, batch, commitment))
+            .buffered(self.concurrency);
+
+        while let Some(batch) = batches.next().await {
+            for (block, transfers) in batch {
+                match transfers {
+                    Ok(transfers) => self.send_transfers(transfers)
+                        .context(concatcp!("err self.send_transfers() in ", FN_CTX))?,
+
+                    Err(e) => {
+                        error!("err fetch_batch() in {}, slot {} queued for retry: {:#?}", FN_CTX, block, e);
+
+                        queue_retry(&self.height_tx, &mut self.retries, block)
+                            .context(concatcp!("err queue_retry() in ", FN_CTX))?;
+                    },
+                }
+
+                self.sequencer.commit(block);
+
+                // Unfinalized blocks keep the height in place, so they are processed again after a restart
+                match self.commitment {
+                    Commitment::Finalized => self.height_tx.send(HeightMsg::Processed(block))
+                        .context(concatcp!("err height_tx.send() in ", FN_CTX))?,
+
+                    Commitment::Confirmed => {
+                        self.unfinalized.insert(block);
+                    },
+                }
+            }
+        }
+
+        // The stream borrows the repo until it is dropped
+        drop(batches);
+
+        // Slots without a block in the range are skipped ones, so there is nothing left to process
+        self.sequencer.commit(end_slot);
+
+        match self.commitment {
+            Commitment::Finalized => self.finalize(end_slot)
+                .context(concatcp!("err self.finalize() in ", FN_CTX))?,
+
+            Commitment::Confirmed => self.process_finality()
+                .await
+                .context(concatcp!("err self.process_finality() in ", FN_CTX))?,
+        }
+
+        Ok(())
+    }
+
+    /// Checks processed blocks against the finalized chain. Transfers from dropped blocks are reverted
+    /// and finalized blocks which were not seen at the commitment are processed now.
+    async fn process_finality(&mut self) -> anyhow::Result<()> {
+        const FN_CTX: &str = "process_finality()";
+
+        let finalized_slot = self.block_repo.get_slot(Commitment::Finalized)
             .await
-            .context(concatcp!("err block_repo.get_block() in ", FN_CTX))?;
+            .context(concatcp!("err block_repo.get_slot() in ", FN_CTX))?;
 
-        for transfer in transfers.into_iter() {
-            let transfer_parsed = transfer.try_into_parsed()
-                .context(concatcp!("err transfer.try_into_parsed() in ", FN_CTX))?;
+        let Some(next_slot) = self.sequencer.next_slot() else {
+            return Ok(());
+        };
 
-            self.transfers_tx.send(transfer_parsed)
+        let end_slot = min(finalized_slot, next_slot.saturating_sub(1));
+
+        let Some((start_slot, end_slot)) = self.finality.next_range(end_slot) else {
+            return Ok(());
+        };
+
+        let canonical: BTreeSet<_> = self.block_repo.get_blocks(start_slot, end_slot, Commitment::Finalized)
+            .await
+            .context(concatcp!("err block_repo.get_blocks() in ", FN_CTX))?
+            .into_iter()
+            .collect();
+
+        let dropped: Vec<_> = self.unfinalized.range(start_slot..=end_slot)
+            .filter(|s| !canonical.contains(s))
+            .copied()
+            .collect();
+
+        for slot in dropped {
+            info!("[{}] - slot {} dropped off the chain, reverting its transfers", FN_CTX, slot);
+
+            self.transfers_tx.send(TransferEvent::Reverted(slot))
                 .context(concatcp!("err transfers_tx.send() in ", FN_CTX))?;
+
+            // A dropped block can never be fetched, so it must not stay in the retries
+            if self.retries.contains(slot) {
+                self.retries.remove(slot);
+                self.height_tx.send(HeightMsg::Retried(slot))
+                    .context(concatcp!("err height_tx.send() in ", FN_CTX))?;
+            }
+
+            counter!("solana_forked_slots_total").increment(1);
+        }
+
+        let missed: Vec<_> = canonical.into_iter()
+            .filter(|s| !self.unfinalized.contains(s))
+            .collect();
+
+        for slot in missed {
+            info!("[{}] - slot {} was finalized without being processed", FN_CTX, slot);
+
+            match fetch_block(&self.block_repo, slot, Commitment::Finalized).await {
+                Ok(transfers) => self.send_transfers(transfers)
+                    .context(concatcp!("err self.send_transfers() in ", FN_CTX))?,
+
+                Err(e) => {
+                    error!("err fetch_block() in {}, slot {} queued for retry: {:#?}", FN_CTX, slot, e);
+
+                    queue_retry(&self.height_tx, &mut self.retries, slot)
+                        .context(concatcp!("err queue_retry() in ", FN_CTX))?;
+                },
+            }
         }
 
-        self.height_tx.send(slot)
+        self.unfinalized = self.unfinalized.split_off(&(end_slot + 1));
+        self.finality.commit(end_slot);
+
+        self.finalize(end_slot)
+            .context(concatcp!("err self.finalize() in ", FN_CTX))?;
+
+        Ok(())
+    }
+
+    /// Every block up to the slot is final, so its transfers can no longer be reverted
+    fn finalize(&self, slot: Slot) -> anyhow::Result<()> {
+        const FN_CTX: &str = "finalize()";
+
+        self.transfers_tx.send(TransferEvent::Finalized(slot))
+            .context(concatcp!("err transfers_tx.send() in ", FN_CTX))?;
+
+        self.height_tx.send(HeightMsg::Processed(slot))
             .context(concatcp!("err height_tx.send() in ", FN_CTX))?;
 
         Ok(())
     }
-}
\ No newline at end of file
+
+    fn send_transfers(&self, transfers: Vec<IncomingTransferParsed>) -> anyhow::Result<()> {
+        for transfer in transfers {
+            self.transfers_tx.send(TransferEvent::Incoming(transfer))
+                .context("err transfers_tx.send() in send_transfers()")?;
+        }
+
+        Ok(())
+    }
+
+    async fn process_retries(&mut self) -> anyhow::Result<()> {
+        const FN_CTX: &str = "process_retries()";
+
+        for slot in self.retries.due(Instant::now()) {
+            match fetch_block(&self.block_repo, slot, self.commitment).await {
+                Ok(transfers) => {
+                    self.send_transfers(transfers)
+                        .context(concatcp!("err self.send_transfers() in ", FN_CTX))?;
+
+                    self.retries.remove(slot);
+
+                    self.height_tx.send(HeightMsg::Retried(slot))
+                        .context(concatcp!("err height_tx.send() in ", FN_CTX))?;
+
+                    info!("[{}] - slot {} processed after retry", FN_CTX, slot);
+                    counter!("solana_block_retries_total", "result" => "ok").increment(1);
+                },
+
+                Err(e) => {
+                    let attempts = self.retries.backoff(slot);
+
+                    error!("err fetch_block() in {}, slot: {}, attempts: {}: {:#?}", FN_CTX, slot, attempts, e);
+                    counter!("solana_block_retries_total", "result" => "err").increment(1);
+                },
+            }
+        }
+
+        gauge!("solana_block_retries_pending").set(self.retries.len() as f64);
+
+        Ok(())
+    }
+}
+
+/// Takes the fields only, so it can be called while the repo is borrowed by the block stream
+fn queue_retry(height_tx: &HeightTx, retries: &mut RetryQueue, slot: Slot) -> anyhow::Result<()> {
+    // Persisted before the height, so the slot survives a restart once the height moves past it
+    height_tx.send(HeightMsg::Failed(slot))
+        .context("err height_tx.send() in queue_retry()")?;
+
+    retries.push(slot);
+
+    counter!("solana_block_failures_total").increment(1);
+    gauge!("solana_block_retries_pending").set(retries.len() as f64);
+
+    Ok(())
+}
+
+async fn fetch_block<B: BlockRepo>(block_repo: &B, slot: Slot, commitment: Commitment) -> anyhow::Result<Vec<IncomingTransferParsed>> {
+    let transfers = block_repo.get_block(slot, commitment)
+        .await
+        .context("err block_repo.get_block() in fetch_block()")?;
+
+    parse_block(slot, transfers)
+}
+
+/// Fetches a batch of blocks, every slot gets its own result, so one bad block does not fail the others
+async fn fetch_batch<B: BlockRepo>(
+    block_repo: &B,
+    slots: Vec<Slot>,
+    commitment: Commitment,
+) -> Vec<(Slot, anyhow::Result<Vec<IncomingTransferParsed>>)> {
+    const FN_CTX: &str = "fetch_batch()";
+
+    if let [slot] = slots[..] {
+        return vec![(slot, fetch_block(block_repo, slot, commitment).await)];
+    }
+
+    match block_repo.get_block_batch(&slots, commitment).await {
+        Ok(blocks) => slots.into_iter()
+            .zip(blocks)
+            .map(|(slot, transfers)| (slot, transfers.and_then(|t| parse_block(slot, t))))
+            .collect(),
+
+        Err(e) => slots.iter()
+            .map(|slot| (*slot, Err(anyhow!("err block_repo.get_block_batch() in {}: {:#}", FN_CTX, e))))
+            .collect(),
+    }
+}
+
+fn parse_block(slot: Slot, transfers: Vec<IncomingTransfer>) -> anyhow::Result<Vec<IncomingTransferParsed>> {
+    // Parse the whole block first, so a failed block is never credited partially
+    let transfers = transfers.into_iter()
+        .map(|t| t.try_into_parsed(slot))
+        .collect::<anyhow::Result<Vec<_>>>()
+        .context("err transfer.try_into_parsed() in parse_block()")?;
+
+    Ok(transfers)
+}
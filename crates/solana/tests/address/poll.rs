@@ -0,0 +1,42 @@
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use app::domain::address::AddressEvent;
+    use solana::{data::address::{poll::AddressPollActor, watch::AddressWatch}, service::parser::to_pubkey};
+    use tokio::{sync::mpsc::unbounded_channel, time::sleep};
+    use tokio_util::sync::CancellationToken;
+
+    const ADDRESS: &str = "7Np41oeYqPefeNQEHSv1UDhYrehxin3NStELsSKCT4K2";
+    const POLL_INTERVAL: Duration = Duration::from_millis(300);
+
+    #[tokio::test]
+    async fn poll_pending_addresses() -> Result<(), anyhow::Error> {
+        let watch = AddressWatch::default();
+        let (address_tx, address_rx) = unbounded_channel();
+        let token = CancellationToken::new();
+
+        let poll_actor = AddressPollActor::new(address_rx, watch.clone(), POLL_INTERVAL);
+        tokio::spawn(poll_actor.start(token.clone()));
+
+        address_tx.send(AddressEvent::Pending(to_pubkey(&ADDRESS.to_string())?))?;
+        sleep(Duration::from_millis(50)).await;
+
+        // A new address is looked up right away, later only once per interval
+        assert_eq!(watch.take_changed().len(), 1);
+        assert!(watch.take_changed().is_empty());
+
+        sleep(POLL_INTERVAL).await;
+        assert_eq!(watch.take_changed().len(), 1);
+
+        address_tx.send(AddressEvent::Completed(to_pubkey(&ADDRESS.to_string())?))?;
+        sleep(POLL_INTERVAL).await;
+
+        assert!(!watch.is_watched(ADDRESS));
+        assert!(watch.take_changed().is_empty());
+
+        token.cancel();
+
+        Ok(())
+    }
+}
@@ -0,0 +1,58 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use solana::data::address::{fetch_signatures, res::SignatureInfo};
+
+    /// Signatures newest first, `before` skips every signature up to the given one
+    fn page(history: &[(&str, u64)], before: Option<&str>, limit: usize) -> Vec<SignatureInfo> {
+        let start = match before {
+            Some(before) => history.iter().position(|(s, _)| *s == before).map_or(history.len(), |i| i + 1),
+            None => 0,
+        };
+
+        history[start..].iter()
+            .take(limit)
+            .map(|(signature, slot)| SignatureInfo { signature: signature.to_string(), slot: *slot, err: None })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn page_until_the_end() -> Result<(), anyhow::Error> {
+        let history = [("e", 15), ("d", 14), ("c", 13), ("b", 12), ("a", 11)];
+        let calls = Mutex::new(Vec::new());
+
+        let signatures = fetch_signatures(0, |before| {
+            calls.lock().unwrap().push(before.clone());
+            let page = page(&history, before.as_deref(), 2);
+            async move { Ok(page) }
+        })
+        .await?;
+
+        let signatures: Vec<_> = signatures.into_iter().map(|s| s.signature).collect();
+        assert_eq!(signatures, vec!["e", "d", "c", "b", "a"]);
+
+        assert_eq!(calls.into_inner()?, vec![None, Some("d".to_string()), Some("b".to_string()), Some("a".to_string())]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn stop_below_start_slot() -> Result<(), anyhow::Error> {
+        let history = [("e", 15), ("d", 14), ("c", 13), ("b", 12), ("a", 11)];
+        let calls = Mutex::new(0);
+
+        let signatures = fetch_signatures(14, |before| {
+            *calls.lock().unwrap() += 1;
+            let page = page(&history, before.as_deref(), 2);
+            async move { Ok(page) }
+        })
+        .await?;
+
+        // The second page reaches below the start slot, older pages are not fetched
+        assert_eq!(signatures.len(), 4);
+        assert_eq!(calls.into_inner()?, 2);
+
+        Ok(())
+    }
+}
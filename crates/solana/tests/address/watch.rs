@@ -0,0 +1,58 @@
+#[cfg(test)]
+mod tests {
+    use app::domain::transfer::{IncomingTransfer, TransferData};
+    use solana::data::address::watch::AddressWatch;
+
+    const ADDRESS: &str = "7Np41oeYqPefeNQEHSv1UDhYrehxin3NStELsSKCT4K2";
+
+    fn transfer(amount: u64) -> IncomingTransfer {
+        IncomingTransfer::new(TransferData::new(ADDRESS.to_string(), amount, None), vec![amount.to_string()])
+    }
+
+    #[test]
+    fn new_address_is_looked_up_once() {
+        let watch = AddressWatch::default();
+
+        assert!(watch.watch(ADDRESS.to_string()));
+        assert!(!watch.watch(ADDRESS.to_string()));
+
+        assert_eq!(watch.take_changed(), vec![(ADDRESS.to_string(), None)]);
+        assert!(watch.take_changed().is_empty());
+
+        watch.set_last_signature(ADDRESS, "signature".to_string());
+        watch.mark_changed(ADDRESS);
+
+        assert_eq!(watch.take_changed(), vec![(ADDRESS.to_string(), Some("signature".to_string()))]);
+    }
+
+    #[test]
+    fn unwatched_address_is_not_marked() {
+        let watch = AddressWatch::default();
+
+        watch.watch(ADDRESS.to_string());
+        watch.unwatch(ADDRESS);
+        watch.mark_changed(ADDRESS);
+
+        assert!(!watch.is_watched(ADDRESS));
+        assert!(watch.take_changed().is_empty());
+    }
+
+    #[test]
+    fn transfers_are_served_by_slot() {
+        let watch = AddressWatch::default();
+
+        watch.push_transfers(5, vec![transfer(1)]);
+        watch.push_transfers(12, vec![transfer(2)]);
+        watch.push_transfers(20, vec![transfer(3)]);
+        watch.push_transfers(25, Vec::new());
+
+        assert_eq!(watch.slots(10, 20), vec![12, 20]);
+
+        watch.drop_before(10);
+
+        assert!(watch.take_transfers(5).is_empty());
+        assert_eq!(watch.take_transfers(12).len(), 1);
+        assert!(watch.take_transfers(12).is_empty());
+        assert_eq!(watch.slots(0, 30), vec![20]);
+    }
+}
@@ -0,0 +1,47 @@
+#[cfg(test)]
+mod tests {
+    use solana::data::block::{parsed::GetParsedBlockRes, res::GetBlockRes};
+
+    const VERSIONED_BLOCK: &str = include_str!("../fixtures/block_versioned_transactions.json");
+    const PARSED_BLOCK: &str = include_str!("../fixtures/block_parsed_instructions.json");
+
+    #[test]
+    fn carry_loaded_account_keys() -> Result<(), anyhow::Error> {
+        let block: GetBlockRes = sonic_rs::from_str(VERSIONED_BLOCK)?;
+
+        let account_keys: Vec<Vec<String>> = block.into_transfers(true, |_| true)
+            .into_iter()
+            .map(|mut t| t.take_account_keys())
+            .collect();
+
+        // Lookup table keys follow the static keys
+        let expected = vec![
+            "3E8kTvGbeXfF3JXsDqY6EnwQyqaz9JuvZ8DaWZGmpKaH".to_string(),
+            "11111111111111111111111111111111".to_string(),
+            "9xQeWvG816bUx9EPjHmaT23yvVM2ZWbrrpZb9PusVFin".to_string(),
+            "7Np41oeYqPefeNQEHSv1UDhYrehxin3NStELsSKCT4K2".to_string(),
+            "SysvarRent111111111111111111111111111111111".to_string(),
+        ];
+
+        assert_eq!(account_keys, vec![expected.clone(), expected]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn carry_parsed_account_keys() -> Result<(), anyhow::Error> {
+        let block: GetParsedBlockRes = sonic_rs::from_str(PARSED_BLOCK)?;
+
+        let transfers = block.into_transfers(true, |_| true);
+        assert!(!transfers.is_empty());
+
+        for mut transfer in transfers {
+            let account_keys = transfer.take_account_keys();
+            let (transfer_data, _) = transfer.expose();
+
+            assert!(account_keys.contains(&transfer_data.address()));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,28 @@
+#[cfg(test)]
+mod tests {
+    use solana::data::block::{parsed::GetParsedBlockRes, res::GetBlockRes};
+
+    const BLOCK: &str = include_str!("../fixtures/block_transfer_sources.json");
+    const PARSED_BLOCK: &str = include_str!("../fixtures/block_parsed_instructions.json");
+
+    #[test]
+    fn carry_block_time() -> Result<(), anyhow::Error> {
+        let block: GetBlockRes = sonic_rs::from_str(BLOCK)?;
+        let block_times: Vec<_> = block.into_transfers(true, |_| true)
+            .iter()
+            .map(|t| t.block_time())
+            .collect();
+
+        assert_eq!(block_times, vec![Some(1718000003); 2]);
+
+        let block: GetParsedBlockRes = sonic_rs::from_str(PARSED_BLOCK)?;
+        let block_times: Vec<_> = block.into_transfers(true, |_| true)
+            .iter()
+            .map(|t| t.block_time())
+            .collect();
+
+        assert_eq!(block_times, vec![Some(1727175030); 4]);
+
+        Ok(())
+    }
+}
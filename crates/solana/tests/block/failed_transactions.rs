@@ -0,0 +1,47 @@
+#[cfg(test)]
+mod tests {
+    use app::domain::transfer::IncomingTransfer;
+    use solana::data::block::res::GetBlockRes;
+
+    const BLOCK: &str = include_str!("../fixtures/block_failed_transactions.json");
+
+    const RECIPIENT: &str = "7Np41oeYqPefeNQEHSv1UDhYrehxin3NStELsSKCT4K2";
+    const SUCCESSFUL_SIGNATURE: &str = "5h6xBEauJ3PK6SWCZ1PGjBvj8vDdWG3KpwATGy1ARAXFSDwt8GFXM7W5Ncn16wmqokgpiKRLuS83KUxyZyv2sUYv";
+
+    fn summarize(transfers: Vec<IncomingTransfer>) -> Vec<(String, u64, Option<String>, String)> {
+        transfers
+            .into_iter()
+            .map(|t| {
+                let (mut transfer_data, signatures) = t.expose();
+                let amount = transfer_data.amount();
+                let mint = transfer_data.take_mint();
+                (transfer_data.address(), amount, mint, signatures[0].clone())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn skip_failed_transactions() -> Result<(), anyhow::Error> {
+        let block: GetBlockRes = sonic_rs::from_str(BLOCK)?;
+        let transfers = summarize(block.into_transfers(true, |_| true));
+
+        assert_eq!(transfers, vec![
+            (RECIPIENT.to_string(), 500_000_000, None, SUCCESSFUL_SIGNATURE.to_string()),
+        ]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn keep_failed_transactions() -> Result<(), anyhow::Error> {
+        let block: GetBlockRes = sonic_rs::from_str(BLOCK)?;
+        let transfers = summarize(block.into_transfers(false, |_| true));
+
+        assert_eq!(transfers.len(), 4);
+        assert!(transfers.iter().any(|(_, amount, mint, _)| *amount == 200_000_000 && mint.is_none()));
+        assert!(transfers.iter().any(|(_, amount, mint, _)| *amount == 300_000_000 && mint.is_none()));
+        assert!(transfers.iter().any(|(_, amount, mint, _)| *amount == 2_500_000 && mint.is_some()));
+
+        Ok(())
+    }
+}
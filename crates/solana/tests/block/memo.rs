@@ -0,0 +1,41 @@
+#[cfg(test)]
+mod tests {
+    use solana::data::block::{parsed::GetParsedBlockRes, res::GetBlockRes};
+
+    const BLOCK: &str = include_str!("../fixtures/block_memo.json");
+    const PARSED_BLOCK: &str = include_str!("../fixtures/block_parsed_instructions.json");
+
+    #[test]
+    fn decode_memo() -> Result<(), anyhow::Error> {
+        let block: GetBlockRes = sonic_rs::from_str(BLOCK)?;
+
+        let memos: Vec<(String, Option<String>)> = block.into_transfers(true, |_| true)
+            .into_iter()
+            .map(|mut t| {
+                let memo = t.take_memo();
+                (t.expose().0.address(), memo)
+            })
+            .collect();
+
+        assert_eq!(memos, vec![
+            ("9xQeWvG816bUx9EPjHmaT23yvVM2ZWbrrpZb9PusVFin".to_string(), Some("order-42".to_string())),
+            ("7Np41oeYqPefeNQEHSv1UDhYrehxin3NStELsSKCT4K2".to_string(), None),
+        ]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn read_parsed_memo() -> Result<(), anyhow::Error> {
+        let block: GetParsedBlockRes = sonic_rs::from_str(PARSED_BLOCK)?;
+
+        let memos: Vec<Option<String>> = block.into_transfers(true, |_| true)
+            .into_iter()
+            .map(|mut t| t.take_memo())
+            .collect();
+
+        assert_eq!(memos, vec![Some("order-42".to_string()), None, None, None]);
+
+        Ok(())
+    }
+}
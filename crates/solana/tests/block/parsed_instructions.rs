@@ -0,0 +1,95 @@
+#[cfg(test)]
+mod tests {
+    use app::domain::transfer::IncomingTransfer;
+    use solana::data::block::parsed::{GetParsedBlockRes, ParsedTransactionRes};
+
+    const BLOCK: &str = include_str!("../fixtures/block_parsed_instructions.json");
+    const TRANSACTION: &str = include_str!("../fixtures/transaction_parsed_instructions.json");
+
+    type Transfer = (String, u64, Option<String>, Option<String>, Vec<String>);
+
+    fn map(block: GetParsedBlockRes, is_pending: impl Fn(&str) -> bool) -> Vec<Transfer> {
+        block.into_transfers(true, is_pending)
+            .into_iter()
+            .map(|t| {
+                let (mut transfer_data, signatures) = t.expose();
+                let (amount, mint, source) = (transfer_data.amount(), transfer_data.take_mint(), transfer_data.take_source());
+                (transfer_data.address(), amount, mint, source, signatures)
+            })
+            .collect()
+    }
+
+    fn credits(transfers: Vec<IncomingTransfer>) -> Vec<(String, u64, Option<i64>, Vec<String>)> {
+        transfers.into_iter()
+            .map(|t| {
+                let block_time = t.block_time();
+                let (transfer_data, signatures) = t.expose();
+                let amount = transfer_data.amount();
+                (transfer_data.address(), amount, block_time, signatures)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn map_system_transfers() -> Result<(), anyhow::Error> {
+        let block: GetParsedBlockRes = sonic_rs::from_str(BLOCK)?;
+        let transfers = map(block, |_| true);
+
+        let first = vec!["3AsdoALgZFuq2oUVWrDYhg2pNeaLJKPLf8hU2mQ6U8qJxeJ6hsrPVpGoBq8xZPbURKZ4DsKmVQcKD2iB3y9PE3bQ".to_string()];
+        let second = vec!["4hXTCkRzt9WyecNzV1XPgCDfGAZzQKNxLXgynz5QDuWWPSAZBZSHptvWRL3BjCvzUXRdKvHL2b7yGrRQcWyaqsaB".to_string()];
+
+        // Memo, compute budget and nonce instructions are passed over, the failed transfer is never credited,
+        // both inner transfers to the same address are a single credit
+        assert_eq!(transfers, vec![
+            (
+                "9xQeWvG816bUx9EPjHmaT23yvVM2ZWbrrpZb9PusVFin".to_string(), 100_000_000, None,
+                Some("2wmVCSfPxGPjrnMMn7rchp4uaeoTqN39mXFC2zhPdri9".to_string()), first,
+            ),
+            (
+                "7Np41oeYqPefeNQEHSv1UDhYrehxin3NStELsSKCT4K2".to_string(), 50_001_000, None,
+                Some("Gq8cNrQuRkC1RZXkVLRfS9KDBWqJ2iH7BWXQoNP5uQ6z".to_string()), second.clone(),
+            ),
+            (
+                "DRpbCBMxVnDK7maPM5tGv6MvB3v1sRMC86PZ8okm21hy".to_string(), 7_000, None,
+                Some("Gq8cNrQuRkC1RZXkVLRfS9KDBWqJ2iH7BWXQoNP5uQ6z".to_string()), second.clone(),
+            ),
+            (
+                "7Np41oeYqPefeNQEHSv1UDhYrehxin3NStELsSKCT4K2".to_string(), 2_000_000,
+                Some("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v".to_string()), None, second,
+            ),
+        ]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn map_transaction_as_in_its_block() -> Result<(), anyhow::Error> {
+        const ADDRESS: &str = "7Np41oeYqPefeNQEHSv1UDhYrehxin3NStELsSKCT4K2";
+
+        let block: GetParsedBlockRes = sonic_rs::from_str(BLOCK)?;
+        let from_block = credits(block.into_transfers(true, |a| a == ADDRESS));
+
+        // Address detection fetches the transaction on its own, it must credit the same amounts
+        let transaction: ParsedTransactionRes = sonic_rs::from_str(TRANSACTION)?;
+        let from_transaction = credits(transaction.into_transfers(true, |a| a == ADDRESS));
+
+        assert_eq!(from_transaction.len(), 2);
+        assert_eq!(from_transaction, from_block);
+
+        Ok(())
+    }
+
+    #[test]
+    fn skip_transfers_to_other_addresses() -> Result<(), anyhow::Error> {
+        let block: GetParsedBlockRes = sonic_rs::from_str(BLOCK)?;
+        let transfers = map(block, |a| a == "DRpbCBMxVnDK7maPM5tGv6MvB3v1sRMC86PZ8okm21hy");
+
+        let addresses: Vec<_> = transfers.into_iter()
+            .map(|(address, amount, ..)| (address, amount))
+            .collect();
+
+        assert_eq!(addresses, vec![("DRpbCBMxVnDK7maPM5tGv6MvB3v1sRMC86PZ8okm21hy".to_string(), 7_000)]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,33 @@
+#[cfg(test)]
+mod tests {
+    use solana::data::{address::pending::PendingAddresses, block::res::GetBlockRes};
+
+    const BLOCK: &str = include_str!("../fixtures/block_versioned_transactions.json");
+    const ADDRESS: &str = "7Np41oeYqPefeNQEHSv1UDhYrehxin3NStELsSKCT4K2";
+
+    #[test]
+    fn drop_addresses_without_payment() -> Result<(), anyhow::Error> {
+        let block: GetBlockRes = sonic_rs::from_str(BLOCK)?;
+
+        let pending = PendingAddresses::default();
+        pending.insert(ADDRESS.to_string());
+
+        let addresses = {
+            let pending = pending.read();
+
+            block.into_transfers(true, |a| pending.contains(a))
+                .into_iter()
+                .map(|t| t.expose().0.address())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(addresses, vec![ADDRESS.to_string()]);
+
+        pending.remove(ADDRESS);
+        let block: GetBlockRes = sonic_rs::from_str(BLOCK)?;
+
+        assert!(block.into_transfers(true, |a| pending.read().contains(a)).is_empty());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,29 @@
+#[cfg(test)]
+mod tests {
+    use solana::data::block::res::GetBlockRes;
+
+    const BLOCK: &str = include_str!("../fixtures/block_accounts_details.json");
+
+    #[test]
+    fn map_accounts_details() -> Result<(), anyhow::Error> {
+        let block: GetBlockRes = sonic_rs::from_str(BLOCK)?;
+
+        let transfers: Vec<(String, u64, Vec<String>)> = block.into_transfers(true, |_| true)
+            .into_iter()
+            .map(|t| {
+                let (transfer_data, signatures) = t.expose();
+                let amount = transfer_data.amount();
+                (transfer_data.address(), amount, signatures)
+            })
+            .collect();
+
+        let signatures = vec!["3AsdoALgZFuq2oUVWrDYhg2pNeaLJKPLf8hU2mQ6U8qJxeJ6hsrPVpGoBq8xZPbURKZ4DsKmVQcKD2iB3y9PE3bQ".to_string()];
+
+        assert_eq!(transfers, vec![
+            ("9xQeWvG816bUx9EPjHmaT23yvVM2ZWbrrpZb9PusVFin".to_string(), 100_000_000, signatures.clone()),
+            ("7Np41oeYqPefeNQEHSv1UDhYrehxin3NStELsSKCT4K2".to_string(), 50_000_000, signatures),
+        ]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,28 @@
+#[cfg(test)]
+mod tests {
+    use solana::data::block::res::GetBlockRes;
+
+    const BLOCK: &str = include_str!("../fixtures/block_transfer_sources.json");
+
+    #[test]
+    fn derive_transfer_sources() -> Result<(), anyhow::Error> {
+        let block: GetBlockRes = sonic_rs::from_str(BLOCK)?;
+
+        let transfers: Vec<(String, u64, Option<String>)> = block.into_transfers(true, |_| true)
+            .into_iter()
+            .map(|t| {
+                let (mut transfer_data, _) = t.expose();
+                let (amount, source) = (transfer_data.amount(), transfer_data.take_source());
+                (transfer_data.address(), amount, source)
+            })
+            .collect();
+
+        // The fee payer only pays the fee, the second signer funds the SOL credit
+        assert_eq!(transfers, vec![
+            ("9xQeWvG816bUx9EPjHmaT23yvVM2ZWbrrpZb9PusVFin".to_string(), 1_500_000_000, Some("2wmVCSfPxGPjrnMMn7rchp4uaeoTqN39mXFC2zhPdri9".to_string())),
+            ("7Np41oeYqPefeNQEHSv1UDhYrehxin3NStELsSKCT4K2".to_string(), 3_000_000, Some("3E8kTvGbeXfF3JXsDqY6EnwQyqaz9JuvZ8DaWZGmpKaH".to_string())),
+        ]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,27 @@
+#[cfg(test)]
+mod tests {
+    use solana::data::block::res::GetBlockRes;
+
+    const BLOCK: &str = include_str!("../fixtures/block_versioned_transactions.json");
+
+    #[test]
+    fn map_loaded_addresses() -> Result<(), anyhow::Error> {
+        let block: GetBlockRes = sonic_rs::from_str(BLOCK)?;
+
+        let transfers: Vec<(String, u64)> = block.into_transfers(true, |_| true)
+            .into_iter()
+            .map(|t| {
+                let (transfer_data, _) = t.expose();
+                let amount = transfer_data.amount();
+                (transfer_data.address(), amount)
+            })
+            .collect();
+
+        assert_eq!(transfers, vec![
+            ("9xQeWvG816bUx9EPjHmaT23yvVM2ZWbrrpZb9PusVFin".to_string(), 100_000_000),
+            ("7Np41oeYqPefeNQEHSv1UDhYrehxin3NStELsSKCT4K2".to_string(), 50_000_000),
+        ]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,36 @@
+#[cfg(test)]
+mod tests {
+    use solana::data::{error::RpcError, res::from_batch_slice};
+
+    #[test]
+    fn key_batch_by_id() -> Result<(), anyhow::Error> {
+        let b = br#"[
+            {"jsonrpc": "2.0", "id": 12, "error": {"code": -32007, "message": "Slot 12 was skipped"}},
+            {"jsonrpc": "2.0", "id": 11, "result": 1}
+        ]"#;
+
+        let batch = from_batch_slice::<u64>(b)?;
+
+        assert!(matches!(batch.get(&11), Some(Ok(1))));
+        assert!(batch.get(&12).is_some_and(|r| r.as_ref().is_err_and(RpcError::is_slot_skipped)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn fail_batch_on_error_without_id() {
+        let b = br#"[
+            {"jsonrpc": "2.0", "id": 11, "result": 1},
+            {"jsonrpc": "2.0", "id": null, "error": {"code": -32600, "message": "Invalid request"}}
+        ]"#;
+
+        let e = from_batch_slice::<u64>(b).unwrap_err();
+        assert!(matches!(e.downcast_ref::<RpcError>(), Some(RpcError::Rpc(r)) if r.code == -32600));
+
+        // A batch the node could not parse is answered by a single error
+        let b = br#"{"jsonrpc": "2.0", "id": null, "error": {"code": -32700, "message": "Parse error"}}"#;
+
+        let e = from_batch_slice::<u64>(b).unwrap_err();
+        assert!(matches!(e.downcast_ref::<RpcError>(), Some(RpcError::Rpc(r)) if r.code == -32700));
+    }
+}
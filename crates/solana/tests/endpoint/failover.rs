@@ -0,0 +1,60 @@
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use solana::data::endpoint::{Endpoint, EndpointPool};
+
+    fn endpoint(name: &str, priority: u32) -> Endpoint {
+        Endpoint {
+            http_url: format!("http://{name}"),
+            ws_url: format!("ws://{name}"),
+            priority,
+            requests_per_second: None,
+            burst: None,
+        }
+    }
+
+    #[test]
+    fn prefer_priority_then_score() -> Result<(), anyhow::Error> {
+        let pool = EndpointPool::new(vec![
+            endpoint("backup", 1),
+            endpoint("slow", 0),
+            endpoint("fast", 0),
+        ])?;
+
+        let slow = pool.select(&[]);
+        pool.report_success(slow, Duration::from_millis(300));
+        let fast = pool.select(&[slow]);
+        pool.report_success(fast, Duration::from_millis(20));
+
+        assert_eq!(pool.http_url(slow), "http://slow");
+        assert_eq!(pool.http_url(fast), "http://fast");
+        assert_eq!(pool.select(&[]), fast);
+
+        Ok(())
+    }
+
+    #[test]
+    fn fail_over_degraded_endpoint() -> Result<(), anyhow::Error> {
+        let pool = EndpointPool::new(vec![
+            endpoint("primary", 0),
+            endpoint("backup", 1),
+        ])?;
+
+        let primary = pool.select(&[]);
+        pool.report_failure(primary);
+        pool.report_failure(primary);
+
+        assert_eq!(pool.select(&[]), primary);
+
+        pool.report_failure(primary);
+
+        let backup = pool.select(&[]);
+        assert_eq!(pool.ws_url(backup), "ws://backup");
+
+        // With every endpoint excluded the pool still has to pick one
+        assert_eq!(pool.select(&[primary, backup]), backup);
+
+        Ok(())
+    }
+}
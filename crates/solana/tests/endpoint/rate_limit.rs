@@ -0,0 +1,33 @@
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use solana::data::endpoint::rate_limit::RateLimiter;
+    use tokio::time::Instant;
+
+    #[tokio::test]
+    async fn wait_after_burst() {
+        let limiter = RateLimiter::new(Some(20), Some(5));
+        let started_at = Instant::now();
+
+        limiter.acquire(5).await;
+        assert!(started_at.elapsed() < Duration::from_millis(20));
+
+        // A batch larger than the burst waits for the whole bucket instead of forever
+        limiter.acquire(10).await;
+        assert!(started_at.elapsed() >= Duration::from_millis(200));
+    }
+
+    #[tokio::test]
+    async fn honor_block() {
+        let limiter = RateLimiter::new(None, None);
+        let started_at = Instant::now();
+
+        limiter.acquire(100).await;
+        assert!(started_at.elapsed() < Duration::from_millis(20));
+
+        limiter.block(Duration::from_millis(100));
+        limiter.acquire(1).await;
+        assert!(started_at.elapsed() >= Duration::from_millis(100));
+    }
+}
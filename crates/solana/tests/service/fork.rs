@@ -0,0 +1,89 @@
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use app::domain::{height::HeightMsg, transfer::{IncomingTransfer, TransferData, TransferEvent}};
+    use solana::{data::{block::BlockRepo, req::Commitment}, domain::slot::Slot, service::transfers::TransfersServiceActor};
+    use tokio::{sync::{mpsc::{channel, unbounded_channel, UnboundedReceiver}, watch}, time::timeout};
+    use tokio_util::sync::CancellationToken;
+
+    const ADDRESS: &str = "7Np41oeYqPefeNQEHSv1UDhYrehxin3NStELsSKCT4K2";
+    const FORKED_SLOT: Slot = 11;
+
+    /// Slot 11 is confirmed with a transfer in it, but never makes it to the finalized chain
+    struct ForkedBlockRepo;
+
+    impl BlockRepo for ForkedBlockRepo {
+        async fn get_block(&self, slot: Slot, _commitment: Commitment) -> anyhow::Result<Vec<IncomingTransfer>> {
+            Ok(match slot {
+                FORKED_SLOT => vec![IncomingTransfer::new(TransferData::new(ADDRESS.to_string(), 1000, None), vec!["signature".to_string()])],
+                _ => Vec::new(),
+            })
+        }
+
+        async fn get_block_batch(&self, slots: &[Slot], commitment: Commitment) -> anyhow::Result<Vec<anyhow::Result<Vec<IncomingTransfer>>>> {
+            let mut blocks = Vec::new();
+
+            for slot in slots {
+                blocks.push(self.get_block(*slot, commitment).await);
+            }
+
+            Ok(blocks)
+        }
+
+        async fn get_blocks(&self, start_slot: Slot, end_slot: Slot, commitment: Commitment) -> anyhow::Result<Vec<Slot>> {
+            let blocks: &[Slot] = match commitment {
+                Commitment::Confirmed => &[10, 11, 12],
+                Commitment::Finalized => &[10, 12],
+            };
+
+            Ok(blocks.iter().copied().filter(|s| (start_slot..=end_slot).contains(s)).collect())
+        }
+
+        async fn get_slot(&self, _commitment: Commitment) -> anyhow::Result<Slot> {
+            Ok(12)
+        }
+    }
+
+    async fn recv<T>(rx: &mut UnboundedReceiver<T>) -> anyhow::Result<T> {
+        timeout(Duration::from_secs(5), rx.recv())
+            .await?
+            .ok_or(anyhow::anyhow!("channel closed"))
+    }
+
+    #[tokio::test]
+    async fn revert_forked_slot() -> Result<(), anyhow::Error> {
+        let (pending_tx, pending_rx) = watch::channel(0);
+        let (slot_tx, mut slot_rx) = channel(1);
+        let (transfers_tx, mut transfers_rx) = unbounded_channel();
+        let (height_tx, mut height_rx) = unbounded_channel();
+
+        let actor = TransfersServiceActor::new(pending_rx, slot_tx, transfers_tx, height_tx, ForkedBlockRepo, Some(9))
+            .with_commitment(Commitment::Confirmed)
+            .with_confirmation_lag(0);
+
+        let token = CancellationToken::new();
+        tokio::spawn(actor.start(token.clone()));
+
+        pending_tx.send(1)?;
+        let tx = slot_rx.recv()
+            .await
+            .ok_or(anyhow::anyhow!("slot subscription missing"))?;
+        tx.send(12)?;
+
+        match recv(&mut transfers_rx).await? {
+            TransferEvent::Incoming(t) => assert_eq!(t.height(), FORKED_SLOT),
+            _ => panic!("expected an incoming transfer"),
+        }
+
+        assert!(matches!(recv(&mut transfers_rx).await?, TransferEvent::Reverted(FORKED_SLOT)));
+        assert!(matches!(recv(&mut transfers_rx).await?, TransferEvent::Finalized(12)));
+
+        // The height only moves once the blocks are checked against the finalized chain
+        assert!(matches!(recv(&mut height_rx).await?, HeightMsg::Processed(12)));
+
+        token.cancel();
+
+        Ok(())
+    }
+}
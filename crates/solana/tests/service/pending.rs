@@ -0,0 +1,53 @@
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use solana::{data::block::mock::BlockServiceMock, domain::slot::SlotTx, service::transfers::TransfersServiceActor};
+    use tokio::{sync::{mpsc::{channel, unbounded_channel, Receiver}, watch}, time::{sleep, timeout}};
+    use tokio_util::sync::CancellationToken;
+
+    const SETTLE_TIME: Duration = Duration::from_millis(100);
+
+    async fn recv(rx: &mut Receiver<SlotTx>) -> anyhow::Result<SlotTx> {
+        timeout(Duration::from_secs(5), rx.recv())
+            .await?
+            .ok_or(anyhow::anyhow!("channel closed"))
+    }
+
+    /// A payment completes and a new one arrives before the actor catches up,
+    /// the subscription must end up open as a payment is still pending
+    #[tokio::test]
+    async fn keep_subscription_for_pending_payment() -> Result<(), anyhow::Error> {
+        let (pending_tx, pending_rx) = watch::channel(0);
+        let (slot_tx, mut slot_rx) = channel(1);
+        let (transfers_tx, _transfers_rx) = unbounded_channel();
+        let (height_tx, _height_rx) = unbounded_channel();
+
+        let block_service = BlockServiceMock::new("address".into(), 1000, "signature".into());
+        let actor = TransfersServiceActor::new(pending_rx, slot_tx, transfers_tx, height_tx, block_service, None);
+
+        let token = CancellationToken::new();
+        tokio::spawn(actor.start(token.clone()));
+
+        pending_tx.send(1)?;
+        let tx = recv(&mut slot_rx).await?;
+
+        pending_tx.send(0)?;
+        pending_tx.send(1)?;
+        sleep(SETTLE_TIME).await;
+
+        let tx = match tx.is_closed() {
+            true => recv(&mut slot_rx).await?,
+            false => tx,
+        };
+        assert!(!tx.is_closed());
+
+        pending_tx.send(0)?;
+        sleep(SETTLE_TIME).await;
+        assert!(tx.is_closed());
+
+        token.cancel();
+
+        Ok(())
+    }
+}
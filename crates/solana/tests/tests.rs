@@ -0,0 +1,28 @@
+mod address {
+    mod poll;
+    mod signatures;
+    mod watch;
+}
+
+mod block {
+    mod account_keys;
+    mod block_time;
+    mod failed_transactions;
+    mod memo;
+    mod parsed_instructions;
+    mod pending_addresses;
+    mod transaction_details;
+    mod transfer_sources;
+    mod versioned_transactions;
+}
+
+mod endpoint {
+    mod batch;
+    mod failover;
+    mod rate_limit;
+}
+
+mod service {
+    mod fork;
+    mod pending;
+}
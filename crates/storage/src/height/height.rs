@@ -1,5 +1,7 @@
+use std::collections::BTreeSet;
+
 use anyhow::{bail, Context};
-use app::domain::height::Height;
+use app::domain::height::{Height, HeightMsg};
 use const_format::concatcp;
 use leveldb::{database::Database, kv::KV, options::{ReadOptions, WriteOptions}};
 use leveldblib::slice_to_arr;
@@ -9,20 +11,25 @@ use tokio_util::sync::CancellationToken;
 use super::models::HeightKey;
 
 pub struct HeightActor {
-    height_rx: UnboundedReceiver<Height>,
+    height_rx: UnboundedReceiver<HeightMsg>,
     db: Database<HeightKey>,
+    retries: BTreeSet<Height>,
 }
 
 impl HeightActor {
-    pub fn new(db: Database<HeightKey>) -> (Self, UnboundedSender<Height>) {
+    pub fn new(db: Database<HeightKey>) -> (Self, UnboundedSender<HeightMsg>) {
         let (tx, height_rx) = unbounded_channel();
-        (Self { height_rx, db }, tx)
+        (Self { height_rx, db, retries: Default::default() }, tx)
     }
 
     pub async fn start(mut self, token: CancellationToken) -> anyhow::Result<()> {
+        self.retries = self.get_retries()?
+            .into_iter()
+            .collect();
+
         loop {
             select! {
-                Some(height) = self.height_rx.recv() => self.set_height(height)?,
+                Some(msg) = self.height_rx.recv() => self.process_height(msg)?,
 
                 _ = token.cancelled() => return Ok(()),
 
@@ -31,6 +38,29 @@ impl HeightActor {
         }
     }
 
+    fn process_height(&mut self, msg: HeightMsg) -> anyhow::Result<()> {
+        const FN_CTX: &str = "process_height()";
+
+        match msg {
+            HeightMsg::Processed(height) => self.set_height(height)
+                .context(concatcp!("err self.set_height() in ", FN_CTX))?,
+
+            HeightMsg::Failed(height) => {
+                self.retries.insert(height);
+                self.set_retries()
+                    .context(concatcp!("err self.set_retries() in ", FN_CTX))?;
+            },
+
+            HeightMsg::Retried(height) => {
+                self.retries.remove(&height);
+                self.set_retries()
+                    .context(concatcp!("err self.set_retries() in ", FN_CTX))?;
+            },
+        }
+
+        Ok(())
+    }
+
     fn set_height(&self, height: Height) -> anyhow::Result<()> {
         let options = WriteOptions::new();
         self.db.put(options, HeightKey::default(), &height.to_le_bytes())
@@ -56,4 +86,35 @@ impl HeightActor {
 
         Ok(height)
     }
-}
\ No newline at end of file
+
+    fn set_retries(&self) -> anyhow::Result<()> {
+        const FN_CTX: &str = "set_retries()";
+
+        let b = sonic_rs::to_vec(&self.retries)
+            .context(concatcp!("err sonic_rs::to_vec() in ", FN_CTX))?;
+
+        let options = WriteOptions::new();
+        self.db.put(options, HeightKey::retries(), &b)
+            .context(concatcp!("err db.put() in ", FN_CTX))?;
+
+        Ok(())
+    }
+
+    /// Returns heights of blocks which failed to process and have to be retried
+    pub fn get_retries(&self) -> anyhow::Result<Vec<Height>> {
+        const FN_CTX: &str = "get_retries()";
+
+        let options = ReadOptions::new();
+        let b = self.db.get_bytes(options, HeightKey::retries())
+            .context(concatcp!("err db.get() in ", FN_CTX))?;
+
+        let retries = match b {
+            Some(b) => sonic_rs::from_slice(&b)
+                .context(concatcp!("err sonic_rs::from_slice() in ", FN_CTX))?,
+
+            None => Vec::new(),
+        };
+
+        Ok(retries)
+    }
+}
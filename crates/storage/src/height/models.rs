@@ -3,9 +3,17 @@ use leveldblib::slice_to_arr;
 use log::error;
 
 const HEIGHT_KEY: u8 = 1;
+const RETRIES_KEY: u8 = 2;
 
 pub struct HeightKey(u8);
 
+impl HeightKey {
+    #[inline]
+    pub fn retries() -> Self {
+        Self(RETRIES_KEY)
+    }
+}
+
 impl Key for HeightKey {
     fn as_slice<T, F: Fn(&[u8]) -> T>(&self, f: F) -> T {
         f(&self.0.to_le_bytes())
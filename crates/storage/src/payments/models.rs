@@ -1,11 +1,50 @@
-use app::domain::pubkey::{Pubkey, ED25519_PUBKEY_LEN, SECP256K1_PUBKEY_LEN};
+use app::domain::{height::Height, payment::PaymentTransfer, pubkey::{Pubkey, ED25519_PUBKEY_LEN, SECP256K1_PUBKEY_LEN}};
 use db_key::Key;
 use hashbrown::HashMap;
 use leveldblib::slice_to_arr;
 use log::error;
 use serde::{Deserialize, Serialize};
 
-#[derive(Hash, PartialEq, Eq)]
+/// Keys of processed signatures start with a byte no UTF-8 id starts with
+const PROCESSED_PREFIX: &[u8] = b"\xffprocessed/";
+
+/// Payments are stored by id, so any number of them may share an address.
+/// Processed signatures of an address are stored next to them under a prefixed key.
+pub struct PaymentKey(Vec<u8>);
+
+impl PaymentKey {
+    #[inline]
+    pub fn id(id: &str) -> Self {
+        Self(id.as_bytes().to_vec())
+    }
+
+    pub fn processed(pubkey: &PubkeyKey) -> Self {
+        Self([PROCESSED_PREFIX, pubkey.as_ref()].concat())
+    }
+
+    /// The address of a processed signatures key, `None` for a payment key
+    pub fn processed_pubkey(&self) -> Option<PubkeyKey> {
+        self.0.strip_prefix(PROCESSED_PREFIX).map(PubkeyKey::from_u8)
+    }
+}
+
+impl Key for PaymentKey {
+    fn as_slice<T, F: Fn(&[u8]) -> T>(&self, f: F) -> T {
+        f(&self.0)
+    }
+
+    fn from_u8(key: &[u8]) -> Self {
+        Self(key.to_vec())
+    }
+}
+
+impl AsRef<[u8]> for PaymentKey {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+#[derive(Hash, PartialEq, Eq, Clone)]
 pub enum PubkeyKey {
     Ed25519([u8; ED25519_PUBKEY_LEN]),
     Secp256k1([u8; SECP256K1_PUBKEY_LEN]),
@@ -48,6 +87,18 @@ impl AsRef<[u8]> for PubkeyKey {
     }
 }
 
+impl PubkeyKey {
+    pub fn to_pubkey(&self) -> Option<Pubkey> {
+        use PubkeyKey::*;
+
+        match self {
+            Ed25519(p) => Some(Pubkey::Ed25519(*p)),
+            Secp256k1(p) => Some(Pubkey::Secp256k1(*p)),
+            Unknown => None,
+        }
+    }
+}
+
 impl Into<PubkeyKey> for Pubkey {
     fn into(self) -> PubkeyKey {
         use Pubkey::*;
@@ -64,13 +115,368 @@ pub struct Payment {
     pub id: String,
     pub tag: u64,
     pub amount: u64,
-    pub signatures: Vec<String>,
+    pub signatures: Vec<PaymentSignature>,
+
+    #[serde(default)]
+    pub mint: Option<String>,
+
+    /// Credits from blocks which are not finalized yet, so they can be reverted on a fork
+    #[serde(default)]
+    pub unfinalized: Vec<Credit>,
+
+    /// Paid in full, the payment is completed once its credits are finalized
+    #[serde(default)]
+    pub paid: bool,
+
+    /// Bytes of the deposit address, empty for payments stored under it before they were stored by id
+    #[serde(default)]
+    pub pubkey: Vec<u8>,
+
+    /// Transfers are only credited if their transaction carries the memo
+    #[serde(default)]
+    pub memo: Option<String>,
+
+    /// Solana Pay reference keys, transfers are only credited if their transaction holds one of them
+    #[serde(default)]
+    pub references: Vec<String>,
+
+    /// Unix timestamp the payment expires at, `None` if it stays open until it is paid
+    #[serde(default)]
+    pub expires_at: Option<u64>,
 }
 
 impl Payment {
-    pub fn new(id: String, tag: u64, amount: u64) -> Self {
-        Self { id, tag, amount, signatures: Default::default() }
+    pub fn new(id: String, tag: u64, amount: u64, mint: Option<String>, pubkey: &PubkeyKey) -> Self {
+        Self {
+            id, tag, amount, mint,
+            signatures: Default::default(),
+            unfinalized: Default::default(),
+            paid: false,
+            pubkey: pubkey.as_ref().to_vec(),
+            memo: None,
+            references: Vec::new(),
+            expires_at: None,
+        }
+    }
+
+    pub fn with_expires_at(mut self, expires_at: Option<u64>) -> Self {
+        self.expires_at = expires_at;
+        self
+    }
+
+    /// Sum of the credited transfers, reverted ones are dropped from them
+    #[inline]
+    pub fn received(&self) -> u64 {
+        self.signatures.iter().map(|s| s.amount).sum()
+    }
+
+    pub fn with_memo(mut self, memo: Option<String>) -> Self {
+        self.memo = memo;
+        self
+    }
+
+    pub fn with_references(mut self, references: Vec<String>) -> Self {
+        self.references = references;
+        self
+    }
+
+    /// Payments waiting for a memo or a reference are not matched by amount
+    #[inline]
+    fn is_tagged(&self) -> bool {
+        self.memo.is_some() || !self.references.is_empty()
+    }
+
+    #[inline]
+    pub fn pubkey(&self) -> PubkeyKey {
+        PubkeyKey::from_u8(&self.pubkey)
+    }
+}
+
+/// A transfer credited to the payment, published with the result
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(from = "StoredSignature")]
+pub struct PaymentSignature {
+    pub signature: String,
+    pub from: Option<String>,
+    pub amount: u64,
+    pub height: Height,
+    pub block_time: Option<i64>,
+}
+
+/// Payments stored before transfers were kept hold plain signatures
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredSignature {
+    Transfer {
+        signature: String,
+        from: Option<String>,
+        amount: u64,
+        height: Height,
+
+        #[serde(default)]
+        block_time: Option<i64>,
+    },
+
+    Signature(String),
+}
+
+impl From<StoredSignature> for PaymentSignature {
+    fn from(stored: StoredSignature) -> Self {
+        match stored {
+            StoredSignature::Transfer { signature, from, amount, height, block_time } => Self { signature, from, amount, height, block_time },
+            StoredSignature::Signature(signature) => Self { signature, from: None, amount: 0, height: 0, block_time: None },
+        }
+    }
+}
+
+impl From<PaymentSignature> for PaymentTransfer {
+    fn from(s: PaymentSignature) -> Self {
+        PaymentTransfer::new(s.signature, s.from, s.amount, s.height, s.block_time)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Credit {
+    pub height: Height,
+
+    /// Amount taken off the remaining amount of the payment, given back on a revert
+    pub amount: u64,
+    pub signatures: Vec<String>,
+}
+
+/// A signature credited to a payment, kept until its block is finalized
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ProcessedSignature {
+    pub signature: String,
+    pub height: Height,
+}
+
+/// Signatures credited on every address. Unfinalized blocks are processed again after a restart,
+/// and a transfer which completed a payment must not be credited to another one of the address.
+#[derive(Default)]
+pub struct ProcessedCache {
+    addresses: HashMap<PubkeyKey, Vec<ProcessedSignature>>,
+}
+
+impl ProcessedCache {
+    #[inline]
+    pub fn insert_address(&mut self, pubkey: PubkeyKey, signatures: Vec<ProcessedSignature>) {
+        self.addresses.insert(pubkey, signatures);
+    }
+
+    pub fn push(&mut self, pubkey: &PubkeyKey, signature: String, height: Height) {
+        self.addresses.entry(pubkey.clone())
+            .or_default()
+            .push(ProcessedSignature { signature, height });
+    }
+
+    pub fn contains(&self, pubkey: &PubkeyKey, signatures: &[String]) -> bool {
+        self.addresses.get(pubkey)
+            .is_some_and(|p| p.iter().any(|s| signatures.contains(&s.signature)))
+    }
+
+    #[inline]
+    pub fn get(&self, pubkey: &PubkeyKey) -> Option<&Vec<ProcessedSignature>> {
+        self.addresses.get(pubkey)
+    }
+
+    /// Drops the signatures the predicate holds for, returns the addresses which changed.
+    /// Addresses left without signatures are removed.
+    pub fn drop_where<F: Fn(&ProcessedSignature) -> bool>(&mut self, f: F) -> Vec<PubkeyKey> {
+        let mut changed = Vec::new();
+
+        for (pubkey, signatures) in self.addresses.iter_mut() {
+            let len = signatures.len();
+            signatures.retain(|s| !f(s));
+
+            if signatures.len() != len {
+                changed.push(pubkey.clone());
+            }
+        }
+
+        self.addresses.retain(|_, s| !s.is_empty());
+
+        changed
     }
 }
 
-pub type PaymentsCache = HashMap<PubkeyKey, Payment>;
\ No newline at end of file
+/// Decides which open payment of an address a transfer is credited to
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Matching {
+    /// The oldest payment
+    #[default]
+    Fifo,
+
+    /// The payment whose remaining amount equals the transfer, the oldest one if there is none
+    Amount,
+
+    /// Payments sharing an address are told apart by their amount. A transfer of the amount up to
+    /// `tolerance` above it completes the payment, an underpayment or a larger overpayment is not credited.
+    Unique { tolerance: u64 },
+}
+
+/// Pending payments by id, with the ids of every address in the order they came in
+/// and the id of every reference key
+#[derive(Default)]
+pub struct PaymentsCache {
+    payments: HashMap<String, Payment>,
+    addresses: HashMap<PubkeyKey, Vec<String>>,
+    references: HashMap<String, String>,
+}
+
+impl PaymentsCache {
+    /// Replaces a payment cached under the same id, the actor keeps a stored payment delivered again
+    pub fn insert(&mut self, payment: Payment) {
+        self.remove(&payment.id);
+
+        self.addresses.entry(payment.pubkey())
+            .or_default()
+            .push(payment.id.clone());
+
+        for reference in &payment.references {
+            self.references.insert(reference.clone(), payment.id.clone());
+        }
+
+        self.payments.insert(payment.id.clone(), payment);
+    }
+
+    pub fn remove(&mut self, id: &str) -> Option<Payment> {
+        let payment = self.payments.remove(id)?;
+        let pubkey = payment.pubkey();
+
+        // The reference may be taken by another payment since
+        for reference in &payment.references {
+            if self.references.get(reference).is_some_and(|i| i == id) {
+                self.references.remove(reference);
+            }
+        }
+
+        if let Some(ids) = self.addresses.get_mut(&pubkey) {
+            ids.retain(|i| i != id);
+
+            if ids.is_empty() {
+                self.addresses.remove(&pubkey);
+            }
+        }
+
+        Some(payment)
+    }
+
+    #[inline]
+    pub fn get_mut(&mut self, id: &str) -> Option<&mut Payment> {
+        self.payments.get_mut(id)
+    }
+
+    #[inline]
+    pub fn contains_address(&self, pubkey: &PubkeyKey) -> bool {
+        self.addresses.contains_key(pubkey)
+    }
+
+    /// Payments of the address, oldest first
+    pub fn by_address<'a>(&'a self, pubkey: &PubkeyKey) -> impl Iterator<Item = &'a Payment> {
+        self.addresses.get(pubkey)
+            .into_iter()
+            .flatten()
+            .filter_map(|id| self.payments.get(id))
+    }
+
+    /// Returns the id of the payment the transfer is credited to. A payment waiting for a reference key
+    /// or a memo takes only transfers whose transaction holds it, ahead of other payments, which are
+    /// matched by `matching`.
+    pub fn select(
+        &self,
+        pubkey: &PubkeyKey,
+        mint: &Option<String>,
+        memo: Option<&str>,
+        account_keys: &[String],
+        amount: u64,
+        matching: Matching,
+    ) -> Option<String> {
+        // The reference must pay the recipient of its payment
+        let referenced = account_keys.iter()
+            .filter_map(|k| self.references.get(k))
+            .filter_map(|id| self.payments.get(id))
+            .find(|p| !p.paid && &p.pubkey() == pubkey && &p.mint == mint);
+
+        if let Some(payment) = referenced {
+            return Some(payment.id.clone());
+        }
+
+        if let Some(memo) = memo {
+            let payment = self.by_address(pubkey)
+                .find(|p| !p.paid && &p.mint == mint && p.references.is_empty() && p.memo.as_deref() == Some(memo));
+
+            if let Some(payment) = payment {
+                return Some(payment.id.clone());
+            }
+        }
+
+        let mut open = self.by_address(pubkey).filter(|p| !p.paid && &p.mint == mint && !p.is_tagged());
+
+        let payment = match matching {
+            Matching::Fifo => open.next(),
+
+            Matching::Amount => {
+                let oldest = open.next()?;
+
+                match oldest.amount == amount {
+                    true => Some(oldest),
+                    false => open.find(|p| p.amount == amount).or(Some(oldest)),
+                }
+            },
+
+            Matching::Unique { tolerance } => open
+                .filter(|p| amount >= p.amount && amount - p.amount <= tolerance)
+                .min_by_key(|p| amount - p.amount),
+        };
+
+        payment.map(|p| p.id.clone())
+    }
+
+    /// Tells whether one of the references already belongs to another payment
+    pub fn references_taken(&self, id: &str, references: &[String]) -> bool {
+        references.iter()
+            .filter_map(|r| self.references.get(r))
+            .any(|i| i != id)
+    }
+
+    /// Tells whether a transfer could match both another open payment of the address and one of `amount`,
+    /// that is whether the amounts are at most `tolerance` apart. Payments waiting for a memo or a reference
+    /// are told apart by it.
+    pub fn conflicts(&self, id: &str, pubkey: &PubkeyKey, mint: &Option<String>, amount: u64, tolerance: u64) -> bool {
+        self.by_address(pubkey)
+            .filter(|p| p.id != id && &p.mint == mint && !p.is_tagged())
+            .any(|p| p.amount.abs_diff(amount) <= tolerance)
+    }
+
+    /// Ids of the payments expired at `now`, paid ones only wait for finality and never expire
+    pub fn expired(&self, now: u64) -> Vec<String> {
+        self.payments.values()
+            .filter(|p| !p.paid && p.expires_at.is_some_and(|e| e <= now))
+            .map(|p| p.id.clone())
+            .collect()
+    }
+
+    /// Ids of the paid payments whose credits are all finalized
+    pub fn finalized(&self) -> Vec<String> {
+        self.payments.values()
+            .filter(|p| p.paid && p.unfinalized.is_empty())
+            .map(|p| p.id.clone())
+            .collect()
+    }
+
+    #[inline]
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Payment> {
+        self.payments.values_mut()
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.payments.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.payments.is_empty()
+    }
+}
\ No newline at end of file
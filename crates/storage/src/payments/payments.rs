@@ -1,30 +1,64 @@
+use std::{cmp::min, time::Duration};
+
 use anyhow::{bail, Context};
-use app::domain::{payment::{IncomingPayment, ProcessedPayment, ProcessedPaymentMeta}, transfer::IncomingTransferParsed};
+use app::domain::{address::{AddressEvent, AddressTx}, height::Height, payment::{unix_now, IncomingPayment, PaymentError, ProcessedPayment}, pending::PendingTx, pubkey::Pubkey, transfer::{IncomingTransferParsed, TransferEvent}};
 use const_format::concatcp;
-use hashbrown::hash_map::EntryRef;
 use leveldb::{database::Database, iterator::Iterable, kv::KV, options::{ReadOptions, WriteOptions}};
-use log::error;
-use tokio::{select, sync::mpsc::{UnboundedReceiver, UnboundedSender}, task};
+use log::{error, info};
+use tokio::{select, sync::mpsc::{UnboundedReceiver, UnboundedSender}, task, time::{interval, MissedTickBehavior}};
 use tokio_util::sync::CancellationToken;
 
-use super::models::{Payment, PaymentsCache, PubkeyKey};
+use super::models::{Credit, Matching, Payment, PaymentKey, PaymentSignature, PaymentsCache, ProcessedCache, ProcessedSignature, PubkeyKey};
+
+const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
 
 pub struct PaymentsActor {
     payments_rx: UnboundedReceiver<IncomingPayment>,
-    transfers_rx: UnboundedReceiver<IncomingTransferParsed>,
-    processed_tx: UnboundedSender<ProcessedPaymentMeta>,
-    db: Database<PubkeyKey>,
+    transfers_rx: UnboundedReceiver<TransferEvent>,
+    processed_tx: UnboundedSender<ProcessedPayment>,
+    pending_tx: PendingTx,
+    db: Database<PaymentKey>,
     cache: PaymentsCache,
+    processed: ProcessedCache,
+    address_tx: Option<AddressTx>,
+    matching: Matching,
+    sweep_interval: Duration,
 }
 
 impl PaymentsActor {
     pub fn new(
         payments_rx: UnboundedReceiver<IncomingPayment>,
-        transfers_rx: UnboundedReceiver<IncomingTransferParsed>,
-        processed_tx: UnboundedSender<ProcessedPaymentMeta>,
-        db: Database<PubkeyKey>,
+        transfers_rx: UnboundedReceiver<TransferEvent>,
+        processed_tx: UnboundedSender<ProcessedPayment>,
+        pending_tx: PendingTx,
+        db: Database<PaymentKey>,
     ) -> Self {
-        Self { payments_rx, transfers_rx, processed_tx, db, cache: Default::default() }
+        Self {
+            payments_rx, transfers_rx, processed_tx, pending_tx, db,
+            cache: Default::default(),
+            processed: Default::default(),
+            address_tx: None,
+            matching: Default::default(),
+            sweep_interval: SWEEP_INTERVAL,
+        }
+    }
+
+    /// Sets how often payments are checked for expiry
+    pub fn with_sweep_interval(mut self, sweep_interval: Duration) -> Self {
+        self.sweep_interval = sweep_interval;
+        self
+    }
+
+    /// Sets how a transfer picks one of several open payments on its address
+    pub fn with_matching(mut self, matching: Matching) -> Self {
+        self.matching = matching;
+        self
+    }
+
+    /// Publishes addresses of pending payments, used by detection strategies watching single addresses
+    pub fn with_address_tx(mut self, address_tx: AddressTx) -> Self {
+        self.address_tx = Some(address_tx);
+        self
     }
 
     pub async fn start(mut self, token: CancellationToken) -> anyhow::Result<()> {
@@ -32,14 +66,21 @@ impl PaymentsActor {
 
         self.load_payments()?;
 
+        let mut sweep_interval = interval(self.sweep_interval);
+        sweep_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
         loop {
             select! {
                 Some(payment) = self.payments_rx.recv() => if let Err(e) = self.process_incoming_payment(payment) {
                     error!("err self.process_incoming_payment() in {}: {:#?}", FN_CTX, e);
                 },
 
-                Some(transfer) = self.transfers_rx.recv() => if let Err(e) = self.process_incoming_transfer(transfer) {
-                    error!("err self.process_incoming_transfer() in {}: {:#?}", FN_CTX, e);
+                Some(event) = self.transfers_rx.recv() => if let Err(e) = self.process_transfer_event(event) {
+                    error!("err self.process_transfer_event() in {}: {:#?}", FN_CTX, e);
+                },
+
+                _ = sweep_interval.tick() => if let Err(e) = self.expire_payments() {
+                    error!("err self.expire_payments() in {}: {:#?}", FN_CTX, e);
                 },
 
                 _ = token.cancelled() => return Ok(()),
@@ -49,52 +90,251 @@ impl PaymentsActor {
         }
     }
 
-    fn process_incoming_payment(&mut self, incoming_payment: IncomingPayment) -> anyhow::Result<()>{
+    fn process_incoming_payment(&mut self, mut incoming_payment: IncomingPayment) -> anyhow::Result<()>{
+        const FN_CTX: &str = "process_incoming_payment()";
+
         let tag = incoming_payment.tag();
-        let (id, transfer_data) = incoming_payment.expose();
+        let expires_at = incoming_payment.expires_at();
+        let memo = incoming_payment.take_memo();
+        let references = incoming_payment.take_references();
+        let (id, mut transfer_data) = incoming_payment.expose();
 
         let amount = transfer_data.amount();
-        let payment = Payment::new(id, tag, amount);
+        let mint = transfer_data.take_mint();
         let pubkey = transfer_data.pubkey().into();
 
-        self.set_payment(&pubkey, &payment)
-            .context("err self.set_payment() in process_incoming_payment()")?;
+        // Input messages are acked once a result is published, so open payments are delivered again
+        // after a reconnect, the stored one keeps its credits and only takes the new delivery tag
+        if let Some(payment) = self.cache.get_mut(&id) {
+            info!("[{}] - payment {} delivered again", FN_CTX, id);
+
+            payment.tag = tag;
+            put_payment(&self.db, payment)
+                .context(concatcp!("err put_payment() in ", FN_CTX))?;
+
+            return Ok(());
+        }
+
+        if let (Matching::Unique { tolerance }, None, true) = (self.matching, &memo, references.is_empty()) {
+            if self.cache.conflicts(&id, &pubkey, &mint, amount, tolerance) {
+                info!("[{}] - payment {} refused, its amount conflicts with another one on the address", FN_CTX, id);
 
-        self.cache.insert(pubkey, payment);
+                let payment = ProcessedPayment::new(id, tag, None, Some(PaymentError::ConflictingAmount), unix_now());
+                self.processed_tx.send(payment)
+                    .context(concatcp!("err processed_tx.send() in ", FN_CTX))?;
+
+                return Ok(());
+            }
+        }
+
+        // A transaction holding a shared reference could not be told apart
+        if self.cache.references_taken(&id, &references) {
+            info!("[{}] - payment {} refused, one of its references belongs to another payment", FN_CTX, id);
+
+            let payment = ProcessedPayment::new(id, tag, None, Some(PaymentError::DuplicateReference), unix_now());
+            self.processed_tx.send(payment)
+                .context(concatcp!("err processed_tx.send() in ", FN_CTX))?;
+
+            return Ok(());
+        }
+
+        let payment = Payment::new(id, tag, amount, mint, &pubkey)
+            .with_memo(memo)
+            .with_references(references)
+            .with_expires_at(expires_at);
+
+        self.set_payment(&payment)
+            .context(concatcp!("err self.set_payment() in ", FN_CTX))?;
+
+        // Watchers only follow addresses, the first payment of one starts it
+        if !self.cache.contains_address(&pubkey) {
+            self.publish_address(&pubkey, AddressEvent::Pending)
+                .context(concatcp!("err self.publish_address() in ", FN_CTX))?;
+        }
+
+        self.cache.insert(payment);
+        self.publish_pending();
 
         Ok(())
     }
 
-    fn process_incoming_transfer(&mut self, incoming_transfer: IncomingTransferParsed) -> anyhow::Result<()> {
+    fn process_transfer_event(&mut self, event: TransferEvent) -> anyhow::Result<()> {
+        match event {
+            TransferEvent::Incoming(transfer) => self.process_incoming_transfer(transfer)
+                .context("err self.process_incoming_transfer() in process_transfer_event()"),
+
+            TransferEvent::Finalized(height) => self.finalize_credits(height)
+                .context("err self.finalize_credits() in process_transfer_event()"),
+
+            TransferEvent::Reverted(height) => self.revert_credits(height)
+                .context("err self.revert_credits() in process_transfer_event()"),
+        }
+    }
+
+    fn process_incoming_transfer(&mut self, mut incoming_transfer: IncomingTransferParsed) -> anyhow::Result<()> {
         const FN_CTX: &str = "process_incoming_transfer()";
 
-        let (transfer_data, signatures) = incoming_transfer.expose();
+        let height = incoming_transfer.height();
+        let block_time = incoming_transfer.block_time();
+        let memo = incoming_transfer.take_memo();
+        let account_keys = incoming_transfer.take_account_keys();
+        let (mut transfer_data, signatures) = incoming_transfer.expose();
         let amount = transfer_data.amount();
-        let pubkey = transfer_data.pubkey().into();
+        let mint = transfer_data.take_mint();
+        let from = transfer_data.take_source();
+        let pubkey: PubkeyKey = transfer_data.pubkey().into();
+
+        // Unfinalized blocks are processed again after a restart, so a transfer may come twice,
+        // also once the payment it was credited to is completed
+        if self.processed.contains(&pubkey, &signatures) {
+            return Ok(());
+        }
 
-        if let EntryRef::Occupied(mut e) = self.cache.entry_ref(&pubkey) {
-            let payment = e.get_mut();
-            payment.signatures.extend(signatures);
+        let Some(id) = self.cache.select(&pubkey, &mint, memo.as_deref(), &account_keys, amount, self.matching) else {
+            return Ok(());
+        };
 
-            match payment.amount.checked_sub(amount) {
-                Some(r) if r > 0 => payment.amount = r,
-                _ => {
-                    let (pubkey, p) = e.remove_entry();
-                    let payment = ProcessedPayment::new(p.id, p.tag, Some(p.signatures), None);
-                    let meta = ProcessedPaymentMeta::new(payment, self.cache.is_empty());
+        let Some(payment) = self.cache.get_mut(&id) else {
+            return Ok(());
+        };
 
-                    self.processed_tx.send(meta)
-                        .context(concatcp!("err processed_tx.send() in ", FN_CTX))?;
+        // The first signature identifies the transaction
+        if let Some(signature) = signatures.first() {
+            payment.signatures.push(PaymentSignature { signature: signature.clone(), from, amount, height, block_time });
+            self.processed.push(&pubkey, signature.clone(), height);
+        }
 
-                    self.remove_payment(&pubkey)
-                        .context(concatcp!("err self.remove_payment() in ", FN_CTX))?;
-                },
+        // A unique amount is paid by the single transfer it was selected for
+        let unique = matches!(self.matching, Matching::Unique { .. }) && payment.memo.is_none() && payment.references.is_empty();
+
+        let taken = match unique {
+            true => 0,
+            false => min(amount, payment.amount),
+        };
+
+        payment.amount -= taken;
+        payment.paid = unique || payment.amount == 0;
+        payment.unfinalized.push(Credit { height, amount: taken, signatures });
+
+        // The result can not be taken back, so it waits until the credits can no longer be reverted
+        if payment.paid {
+            info!("[{}] - payment {} paid, completed once its credits are finalized", FN_CTX, id);
+        }
+
+        put_payment(&self.db, payment)
+            .context(concatcp!("err put_payment() in ", FN_CTX))?;
+
+        self.put_processed(&pubkey)
+            .context(concatcp!("err self.put_processed() in ", FN_CTX))?;
+
+        Ok(())
+    }
+
+    /// Closes the payment, `error` tells why it was closed before it was paid in full
+    fn complete_payment(&mut self, id: &str, error: Option<PaymentError>) -> anyhow::Result<()> {
+        const FN_CTX: &str = "complete_payment()";
+
+        let Some(p) = self.cache.remove(id) else {
+            return Ok(());
+        };
+
+        let pubkey = p.pubkey();
+        let received = error.map(|_| p.received());
+        let signatures = p.signatures.into_iter().map(Into::into).collect();
+        let payment = ProcessedPayment::new(p.id, p.tag, Some(signatures), error, unix_now())
+            .with_received(received);
+        self.publish_pending();
+
+        self.processed_tx.send(payment)
+            .context(concatcp!("err processed_tx.send() in ", FN_CTX))?;
+
+        self.remove_payment(id)
+            .context(concatcp!("err self.remove_payment() in ", FN_CTX))?;
+
+        // Other payments on the address keep it watched
+        if !self.cache.contains_address(&pubkey) {
+            self.publish_address(&pubkey, AddressEvent::Completed)
+                .context(concatcp!("err self.publish_address() in ", FN_CTX))?;
+        }
+
+        Ok(())
+    }
+
+    /// Closes payments past their expiry with whatever was credited to them
+    fn expire_payments(&mut self) -> anyhow::Result<()> {
+        const FN_CTX: &str = "expire_payments()";
+
+        for id in self.cache.expired(unix_now()) {
+            info!("[{}] - payment {} expired", FN_CTX, id);
+
+            self.complete_payment(&id, Some(PaymentError::Expired))
+                .context(concatcp!("err self.complete_payment() in ", FN_CTX))?;
+        }
+
+        Ok(())
+    }
+
+    /// Credits up to the height can no longer be reverted, so they are dropped from the payments
+    /// and paid payments are completed
+    fn finalize_credits(&mut self, height: Height) -> anyhow::Result<()> {
+        const FN_CTX: &str = "finalize_credits()";
+
+        for payment in self.cache.iter_mut() {
+            let len = payment.unfinalized.len();
+            payment.unfinalized.retain(|c| c.height > height);
+
+            if payment.unfinalized.len() != len {
+                put_payment(&self.db, payment)
+                    .context(concatcp!("err put_payment() in ", FN_CTX))?;
             }
         }
 
-        if let Some(payment) = self.cache.get(&pubkey) {
-            self.set_payment(&pubkey, payment)
-                .context(concatcp!("err self.set_payment() in ", FN_CTX))?;
+        for id in self.cache.finalized() {
+            self.complete_payment(&id, None)
+                .context(concatcp!("err self.complete_payment() in ", FN_CTX))?;
+        }
+
+        // Finalized blocks are never processed again
+        for pubkey in self.processed.drop_where(|s| s.height <= height) {
+            self.put_processed(&pubkey)
+                .context(concatcp!("err self.put_processed() in ", FN_CTX))?;
+        }
+
+        Ok(())
+    }
+
+    /// Takes back the credits of a block which dropped off the canonical chain. Payments are only
+    /// completed once their credits are finalized, so a paid payment is opened again.
+    fn revert_credits(&mut self, height: Height) -> anyhow::Result<()> {
+        const FN_CTX: &str = "revert_credits()";
+
+        for payment in self.cache.iter_mut() {
+            if !payment.unfinalized.iter().any(|c| c.height == height) {
+                continue;
+            }
+
+            let (reverted, unfinalized) = payment.unfinalized
+                .drain(..)
+                .partition::<Vec<_>, _>(|c| c.height == height);
+
+            payment.unfinalized = unfinalized;
+
+            for credit in reverted {
+                payment.amount += credit.amount;
+                payment.paid = false;
+                payment.signatures.retain(|p| !credit.signatures.contains(&p.signature));
+
+                info!("[{}] - payment {} credit of {} reverted, height: {}", FN_CTX, payment.id, credit.amount, height);
+            }
+
+            put_payment(&self.db, payment)
+                .context(concatcp!("err put_payment() in ", FN_CTX))?;
+        }
+
+        // The transfers may come again in another block
+        for pubkey in self.processed.drop_where(|s| s.height == height) {
+            self.put_processed(&pubkey)
+                .context(concatcp!("err self.put_processed() in ", FN_CTX))?;
         }
 
         Ok(())
@@ -104,18 +344,67 @@ impl PaymentsActor {
         const FN_CTX: &str = "load_payments()";
 
         let options = ReadOptions::new();
+        let keys: Vec<PaymentKey> = self.db.keys_iter(options).collect();
+
+        for k in keys {
+            if let Some(pubkey) = k.processed_pubkey() {
+                let signatures = self.get_processed(&k)
+                    .context(concatcp!("err self.get_processed() in ", FN_CTX))?;
+
+                self.processed.insert_address(pubkey, signatures);
+                continue;
+            }
 
-        for k in self.db.keys_iter(options) {
-            let payment = self.get_payment(&k)
+            let mut payment = self.get_payment(&k)
                 .context(concatcp!("err self.get_payment() in ", FN_CTX))?;
 
-            self.cache.insert(k, payment);
+            // Payments used to be stored under their address, they are moved under their id
+            if payment.pubkey.is_empty() {
+                payment.pubkey = k.as_ref().to_vec();
+
+                self.set_payment(&payment)
+                    .context(concatcp!("err self.set_payment() in ", FN_CTX))?;
+
+                let options = WriteOptions::new();
+                self.db.delete(options, &k)
+                    .context(concatcp!("err db.delete() in ", FN_CTX))?;
+
+                info!("[{}] - payment {} moved under its id", FN_CTX, payment.id);
+            }
+
+            let pubkey = payment.pubkey();
+
+            if !self.cache.contains_address(&pubkey) {
+                self.publish_address(&pubkey, AddressEvent::Pending)
+                    .context(concatcp!("err self.publish_address() in ", FN_CTX))?;
+            }
+
+            self.cache.insert(payment);
         }
 
+        self.publish_pending();
+
+        Ok(())
+    }
+
+    /// The count changes together with the cache, so a subscriber never sees it drop to zero while a payment is pending
+    #[inline]
+    fn publish_pending(&self) {
+        self.pending_tx.send_replace(self.cache.len());
+    }
+
+    fn publish_address(&self, pubkey: &PubkeyKey, event: fn(Pubkey) -> AddressEvent) -> anyhow::Result<()> {
+        let (Some(address_tx), Some(pubkey)) = (&self.address_tx, pubkey.to_pubkey()) else {
+            return Ok(());
+        };
+
+        address_tx.send(event(pubkey))
+            .context("err address_tx.send() in publish_address()")?;
+
         Ok(())
     }
 
-    fn get_payment(&self, key: &PubkeyKey) -> anyhow::Result<Payment> {
+    fn get_payment(&self, key: &PaymentKey) -> anyhow::Result<Payment> {
         const FN_CTX: &str = "get_payment()";
 
         let options = ReadOptions::new();
@@ -132,26 +421,72 @@ impl PaymentsActor {
         Ok(payment)
     }
 
-    fn set_payment(&self, pubkey: &PubkeyKey, payment: &Payment) -> anyhow::Result<()> {
-        const FN_CTX: &str = "set_payment()";
+    fn get_processed(&self, key: &PaymentKey) -> anyhow::Result<Vec<ProcessedSignature>> {
+        const FN_CTX: &str = "get_processed()";
+
+        let options = ReadOptions::new();
+        let b = self.db.get_bytes(options, key)
+            .context(concatcp!("err db.get() in ", FN_CTX))?;
+
+        let signatures = match b {
+            Some(b) => sonic_rs::from_slice(&b)
+                .context(concatcp!("err sonic_rs::from_slice() in ", FN_CTX))?,
+
+            None => bail!("err 'the key is invalid' in {}", FN_CTX),
+        };
+
+        Ok(signatures)
+    }
+
+    /// Stores the processed signatures of the address, the record is deleted once none are left
+    fn put_processed(&self, pubkey: &PubkeyKey) -> anyhow::Result<()> {
+        const FN_CTX: &str = "put_processed()";
+
+        let Some(signatures) = self.processed.get(pubkey) else {
+            let options = WriteOptions::new();
+            self.db.delete(options, PaymentKey::processed(pubkey))
+                .context(concatcp!("err db.delete() in ", FN_CTX))?;
+
+            return Ok(());
+        };
 
-        let b = sonic_rs::to_vec(payment)
+        let b = sonic_rs::to_vec(signatures)
             .context(concatcp!("err sonic_rs::to_vec() in ", FN_CTX))?;
 
         let options = WriteOptions::new();
-        self.db.put(options, pubkey, &b)
+        self.db.put(options, PaymentKey::processed(pubkey), &b)
             .context(concatcp!("err db.put() in ", FN_CTX))?;
 
         Ok(())
     }
 
-    fn remove_payment(&self, pubkey: &PubkeyKey) -> anyhow::Result<()>{
+    #[inline]
+    fn set_payment(&self, payment: &Payment) -> anyhow::Result<()> {
+        put_payment(&self.db, payment)
+            .context("err put_payment() in set_payment()")
+    }
+
+    fn remove_payment(&self, id: &str) -> anyhow::Result<()>{
         const FN_CTX: &str = "remove_payment()";
 
         let options = WriteOptions::new();
-        self.db.delete(options, pubkey)
+        self.db.delete(options, PaymentKey::id(id))
             .context(concatcp!("err db.delete() in ", FN_CTX))?;
 
         Ok(())
     }
+}
+
+/// Takes the database only, so payments can be stored while the cache is borrowed
+fn put_payment(db: &Database<PaymentKey>, payment: &Payment) -> anyhow::Result<()> {
+    const FN_CTX: &str = "put_payment()";
+
+    let b = sonic_rs::to_vec(payment)
+        .context(concatcp!("err sonic_rs::to_vec() in ", FN_CTX))?;
+
+    let options = WriteOptions::new();
+    db.put(options, PaymentKey::id(&payment.id), &b)
+        .context(concatcp!("err db.put() in ", FN_CTX))?;
+
+    Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,253 @@
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, time::Duration};
+
+    use app::domain::{payment::{unix_now, IncomingPayment, PaymentError, ProcessedPayment}, pubkey::Pubkey, transfer::{IncomingTransferParsed, TransferDataParsed, TransferEvent}};
+    use storage::payments::{models::PaymentKey, PaymentsActor};
+    use tokio::{sync::{mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender}, watch}, task::JoinHandle, time::timeout};
+    use tokio_util::sync::CancellationToken;
+
+    const PUBKEY: Pubkey = Pubkey::Ed25519([1; 32]);
+
+    struct Harness {
+        payments_tx: UnboundedSender<IncomingPayment>,
+        transfers_tx: UnboundedSender<TransferEvent>,
+        processed_rx: UnboundedReceiver<ProcessedPayment>,
+        pending_rx: watch::Receiver<usize>,
+        token: CancellationToken,
+        handle: JoinHandle<anyhow::Result<()>>,
+    }
+
+    impl Harness {
+        fn start(path: &str) -> anyhow::Result<Self> {
+            let (payments_tx, payments_rx) = unbounded_channel();
+            let (transfers_tx, transfers_rx) = unbounded_channel();
+            let (processed_tx, processed_rx) = unbounded_channel();
+            let (pending_tx, pending_rx) = watch::channel(0);
+
+            let db = leveldblib::connect::<PaymentKey>(path)?;
+            let token = CancellationToken::new();
+            let handle = tokio::spawn(PaymentsActor::new(payments_rx, transfers_rx, processed_tx, pending_tx, db)
+                .with_sweep_interval(Duration::from_millis(100))
+                .start(token.clone()));
+
+            Ok(Self { payments_tx, transfers_tx, processed_rx, pending_rx, token, handle })
+        }
+
+        /// Waits for the actor to close, so the database can be opened again
+        async fn stop(self) -> anyhow::Result<()> {
+            self.token.cancel();
+            self.handle.await?
+        }
+
+        async fn pay(&mut self, id: &str, amount: u64) -> anyhow::Result<()> {
+            self.pay_until(id, amount, None).await
+        }
+
+        /// Payments and transfers come over separate channels, so the payment is awaited before a transfer may follow
+        async fn pay_until(&mut self, id: &str, amount: u64, expires_at: Option<u64>) -> anyhow::Result<()> {
+            let pending = *self.pending_rx.borrow();
+            self.send_payment(id, 1, amount, expires_at)?;
+
+            timeout(Duration::from_secs(1), self.pending_rx.wait_for(|p| *p > pending)).await??;
+            Ok(())
+        }
+
+        fn send_payment(&self, id: &str, tag: u64, amount: u64, expires_at: Option<u64>) -> anyhow::Result<()> {
+            let payment = IncomingPayment::new(id.to_string(), tag, TransferDataParsed::new(PUBKEY, amount, None))
+                .with_expires_at(expires_at);
+
+            self.payments_tx.send(payment)?;
+            Ok(())
+        }
+
+        fn transfer(&self, signature: &str, amount: u64, height: u64) -> anyhow::Result<()> {
+            let transfer = IncomingTransferParsed::new(TransferDataParsed::new(PUBKEY, amount, None), vec![signature.to_string()], height);
+            self.transfers_tx.send(TransferEvent::Incoming(transfer))?;
+            Ok(())
+        }
+
+        async fn recv(&mut self) -> anyhow::Result<ProcessedPayment> {
+            timeout(Duration::from_secs(5), self.processed_rx.recv())
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("processed channel closed"))
+        }
+
+        /// The actor handles messages in order, so once the count reflects them nothing is left to publish
+        async fn settle(&mut self, pending: usize) -> anyhow::Result<()> {
+            timeout(Duration::from_secs(1), self.pending_rx.wait_for(|p| *p == pending)).await??;
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            Ok(())
+        }
+    }
+
+    fn db_path(name: &str) -> String {
+        let path = env::temp_dir().join(format!("txchecker-payments-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        path.to_string_lossy().into_owned()
+    }
+
+    fn signatures(payment: &mut ProcessedPayment) -> Vec<String> {
+        payment.take_signatures()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|t| t.expose().0)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn reopen_paid_payment_on_fork() -> anyhow::Result<()> {
+        let path = db_path("fork");
+        let mut harness = Harness::start(&path)?;
+
+        harness.pay("first", 100).await?;
+        harness.transfer("forked", 100, 10)?;
+        harness.settle(1).await?;
+
+        // Paid, but the block is not finalized yet
+        assert!(harness.processed_rx.try_recv().is_err());
+
+        harness.transfers_tx.send(TransferEvent::Reverted(10))?;
+        harness.transfers_tx.send(TransferEvent::Finalized(12))?;
+        harness.settle(1).await?;
+
+        // The forked credit never completes the payment
+        assert!(harness.processed_rx.try_recv().is_err());
+
+        harness.transfer("canonical", 100, 13)?;
+        harness.transfers_tx.send(TransferEvent::Finalized(13))?;
+
+        let mut payment = harness.recv().await?;
+        assert_eq!(signatures(&mut payment), vec!["canonical".to_string()]);
+
+        harness.stop().await?;
+        fs::remove_dir_all(&path)?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn skip_replayed_transfer_after_restart() -> anyhow::Result<()> {
+        let path = db_path("replay");
+
+        let mut harness = Harness::start(&path)?;
+        harness.pay("first", 100).await?;
+        harness.pay("second", 100).await?;
+        harness.transfer("paid", 100, 10)?;
+        harness.settle(2).await?;
+        harness.stop().await?;
+
+        // The block is not finalized, so it is processed again
+        let mut harness = Harness::start(&path)?;
+        harness.transfer("paid", 100, 10)?;
+        harness.transfers_tx.send(TransferEvent::Finalized(10))?;
+
+        let mut payment = harness.recv().await?;
+        assert_eq!(payment.tag(), 1);
+        assert_eq!(signatures(&mut payment), vec!["paid".to_string()]);
+        assert_eq!(payment.id(), "first");
+
+        harness.settle(1).await?;
+        assert!(harness.processed_rx.try_recv().is_err());
+
+        harness.stop().await?;
+        fs::remove_dir_all(&path)?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn keep_credits_of_redelivered_payment() -> anyhow::Result<()> {
+        let path = db_path("redelivery");
+
+        let mut harness = Harness::start(&path)?;
+        harness.pay("first", 200).await?;
+        harness.pay("second", 100).await?;
+        harness.transfer("partial", 100, 10)?;
+        harness.settle(2).await?;
+        harness.stop().await?;
+
+        // Open payments are not acked, so the queue delivers them again with a new tag
+        let mut harness = Harness::start(&path)?;
+        harness.send_payment("first", 2, 200, None)?;
+        harness.transfer("partial", 100, 10)?;
+        harness.transfer("rest", 100, 11)?;
+        harness.transfers_tx.send(TransferEvent::Finalized(11))?;
+
+        let mut payment = harness.recv().await?;
+        assert_eq!(payment.tag(), 2);
+        assert_eq!(signatures(&mut payment), vec!["partial".to_string(), "rest".to_string()]);
+        assert_eq!(payment.id(), "first");
+
+        harness.settle(1).await?;
+        assert!(harness.processed_rx.try_recv().is_err());
+
+        harness.stop().await?;
+        fs::remove_dir_all(&path)?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn publish_expired_payment() -> anyhow::Result<()> {
+        let path = db_path("expired");
+        let now = unix_now();
+
+        let mut harness = Harness::start(&path)?;
+        // Already expired, it may be swept before the pending count is seen
+        harness.pay("second", 100).await?;
+        harness.send_payment("first", 1, 100, Some(now - 1))?;
+
+        let mut payment = harness.recv().await?;
+        assert_eq!(payment.take_error(), Some(PaymentError::Expired));
+        assert_eq!(payment.received(), Some(0));
+        assert!(signatures(&mut payment).is_empty());
+        assert_eq!(payment.id(), "first");
+
+        harness.settle(1).await?;
+        harness.stop().await?;
+
+        // The expired payment is removed from the database as well
+        let mut harness = Harness::start(&path)?;
+        harness.settle(1).await?;
+
+        assert!(harness.processed_rx.try_recv().is_err());
+
+        harness.stop().await?;
+        fs::remove_dir_all(&path)?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn skip_replayed_transfer_of_expired_payment() -> anyhow::Result<()> {
+        let path = db_path("replay-expired");
+        let now = unix_now();
+
+        let mut harness = Harness::start(&path)?;
+        harness.pay_until("first", 200, Some(now + 2)).await?;
+        harness.pay("second", 100).await?;
+        harness.transfer("partial", 100, 10)?;
+
+        let mut payment = harness.recv().await?;
+        assert_eq!(payment.received(), Some(100));
+        assert_eq!(signatures(&mut payment), vec!["partial".to_string()]);
+        assert_eq!(payment.id(), "first");
+
+        harness.settle(1).await?;
+        harness.stop().await?;
+
+        // The credit of the expired payment is not taken by the other one on the address
+        let mut harness = Harness::start(&path)?;
+        harness.transfer("partial", 100, 10)?;
+        harness.transfers_tx.send(TransferEvent::Finalized(10))?;
+        harness.settle(1).await?;
+
+        assert!(harness.processed_rx.try_recv().is_err());
+
+        harness.stop().await?;
+        fs::remove_dir_all(&path)?;
+
+        Ok(())
+    }
+}
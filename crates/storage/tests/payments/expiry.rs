@@ -0,0 +1,42 @@
+#[cfg(test)]
+mod tests {
+    use app::domain::pubkey::Pubkey;
+    use storage::payments::models::{Payment, PaymentSignature, PaymentsCache, PubkeyKey};
+
+    #[test]
+    fn select_expired_payments() {
+        let pubkey: PubkeyKey = Pubkey::Ed25519([1; 32]).into();
+        let mut cache = PaymentsCache::default();
+
+        cache.insert(Payment::new("open".to_string(), 1, 300, None, &pubkey));
+        cache.insert(Payment::new("early".to_string(), 2, 200, None, &pubkey).with_expires_at(Some(100)));
+        cache.insert(Payment::new("late".to_string(), 3, 200, None, &pubkey).with_expires_at(Some(200)));
+
+        assert!(cache.expired(99).is_empty());
+        assert_eq!(cache.expired(100), vec!["early".to_string()]);
+
+        let mut expired = cache.expired(200);
+        expired.sort();
+        assert_eq!(expired, vec!["early".to_string(), "late".to_string()]);
+    }
+
+    #[test]
+    fn sum_received_amount() {
+        let pubkey: PubkeyKey = Pubkey::Ed25519([1; 32]).into();
+        let mut payment = Payment::new("partial".to_string(), 1, 300, None, &pubkey);
+
+        assert_eq!(payment.received(), 0);
+
+        for (signature, amount) in [("a", 100), ("b", 50)] {
+            payment.signatures.push(PaymentSignature {
+                signature: signature.to_string(),
+                from: None,
+                amount,
+                height: 1,
+                block_time: None,
+            });
+        }
+
+        assert_eq!(payment.received(), 150);
+    }
+}
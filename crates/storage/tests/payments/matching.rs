@@ -0,0 +1,139 @@
+#[cfg(test)]
+mod tests {
+    use app::domain::pubkey::Pubkey;
+    use storage::payments::models::{Matching, Payment, PaymentsCache, PubkeyKey};
+
+    fn cache(pubkey: &PubkeyKey) -> PaymentsCache {
+        let mut cache = PaymentsCache::default();
+
+        cache.insert(Payment::new("first".to_string(), 1, 300, None, pubkey));
+        cache.insert(Payment::new("second".to_string(), 2, 200, None, pubkey));
+        cache.insert(Payment::new("token".to_string(), 3, 200, Some("mint".to_string()), pubkey));
+
+        cache
+    }
+
+    #[test]
+    fn keep_payments_sharing_an_address() {
+        let pubkey: PubkeyKey = Pubkey::Ed25519([1; 32]).into();
+        let mut cache = cache(&pubkey);
+
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.select(&pubkey, &None, None, &[], 200, Matching::Fifo).as_deref(), Some("first"));
+
+        cache.remove("first");
+        assert_eq!(cache.select(&pubkey, &None, None, &[], 200, Matching::Fifo).as_deref(), Some("second"));
+
+        cache.remove("second");
+        cache.remove("token");
+        assert!(!cache.contains_address(&pubkey));
+    }
+
+    #[test]
+    fn match_by_amount() {
+        let pubkey: PubkeyKey = Pubkey::Ed25519([1; 32]).into();
+        let cache = cache(&pubkey);
+
+        assert_eq!(cache.select(&pubkey, &None, None, &[], 200, Matching::Amount).as_deref(), Some("second"));
+        assert_eq!(cache.select(&pubkey, &Some("mint".to_string()), None, &[], 200, Matching::Amount).as_deref(), Some("token"));
+
+        // Without an exact match the oldest payment is credited
+        assert_eq!(cache.select(&pubkey, &None, None, &[], 100, Matching::Amount).as_deref(), Some("first"));
+
+        let other: PubkeyKey = Pubkey::Ed25519([2; 32]).into();
+        assert_eq!(cache.select(&other, &None, None, &[], 200, Matching::Amount), None);
+    }
+
+    #[test]
+    fn match_unique_amount() {
+        let pubkey: PubkeyKey = Pubkey::Ed25519([1; 32]).into();
+        let cache = cache(&pubkey);
+        let matching = Matching::Unique { tolerance: 10 };
+
+        assert_eq!(cache.select(&pubkey, &None, None, &[], 305, matching).as_deref(), Some("first"));
+        assert_eq!(cache.select(&pubkey, &None, None, &[], 250, matching), None);
+
+        // The amount itself up to the tolerance above it
+        assert_eq!(cache.select(&pubkey, &None, None, &[], 200, matching).as_deref(), Some("second"));
+        assert_eq!(cache.select(&pubkey, &None, None, &[], 210, matching).as_deref(), Some("second"));
+        assert_eq!(cache.select(&pubkey, &None, None, &[], 211, matching), None);
+
+        // Underpayments are never credited
+        assert_eq!(cache.select(&pubkey, &None, None, &[], 199, matching), None);
+    }
+
+    #[test]
+    fn detect_conflicting_amount() {
+        let pubkey: PubkeyKey = Pubkey::Ed25519([1; 32]).into();
+        let cache = cache(&pubkey);
+
+        // A transfer of 210 could pay both 200 and 210, and one of 200 both 190 and 200
+        assert!(cache.conflicts("new", &pubkey, &None, 210, 10));
+        assert!(!cache.conflicts("new", &pubkey, &None, 211, 10));
+        assert!(cache.conflicts("new", &pubkey, &None, 190, 10));
+        assert!(!cache.conflicts("new", &pubkey, &None, 189, 10));
+
+        // The payment may come again under its own id, and other mints do not count
+        assert!(!cache.conflicts("second", &pubkey, &None, 200, 10));
+        assert!(!cache.conflicts("new", &pubkey, &Some("other".to_string()), 200, 10));
+    }
+
+    #[test]
+    fn match_memo() {
+        let pubkey: PubkeyKey = Pubkey::Ed25519([1; 32]).into();
+        let mut cache = cache(&pubkey);
+        cache.insert(Payment::new("memo".to_string(), 4, 500, None, &pubkey).with_memo(Some("order-42".to_string())));
+
+        // The memo wins over older payments, which take transfers without it
+        assert_eq!(cache.select(&pubkey, &None, Some("order-42"), &[], 200, Matching::Fifo).as_deref(), Some("memo"));
+        assert_eq!(cache.select(&pubkey, &None, Some("order-7"), &[], 200, Matching::Fifo).as_deref(), Some("first"));
+        assert_eq!(cache.select(&pubkey, &None, None, &[], 500, Matching::Amount).as_deref(), Some("first"));
+
+        cache.remove("first");
+        cache.remove("second");
+        assert_eq!(cache.select(&pubkey, &None, None, &[], 500, Matching::Fifo), None);
+    }
+
+    #[test]
+    fn match_reference() {
+        let pubkey: PubkeyKey = Pubkey::Ed25519([1; 32]).into();
+        let other: PubkeyKey = Pubkey::Ed25519([2; 32]).into();
+        let mut cache = cache(&pubkey);
+        cache.insert(Payment::new("reference".to_string(), 4, 500, None, &pubkey).with_references(vec!["ref".to_string()]));
+
+        let account_keys = ["payer".to_string(), "ref".to_string()];
+
+        // The reference wins over older payments, which take transfers without it
+        assert_eq!(cache.select(&pubkey, &None, None, &account_keys, 200, Matching::Fifo).as_deref(), Some("reference"));
+        assert_eq!(cache.select(&pubkey, &None, None, &[], 500, Matching::Amount).as_deref(), Some("first"));
+
+        // The reference only credits transfers to the address of its payment
+        assert_eq!(cache.select(&other, &None, None, &account_keys, 500, Matching::Fifo), None);
+
+        cache.remove("reference");
+        assert_eq!(cache.select(&pubkey, &None, None, &account_keys, 200, Matching::Fifo).as_deref(), Some("first"));
+    }
+
+    #[test]
+    fn keep_reference_of_remaining_payment() {
+        let pubkey: PubkeyKey = Pubkey::Ed25519([1; 32]).into();
+        let mut cache = PaymentsCache::default();
+        cache.insert(Payment::new("first".to_string(), 1, 100, None, &pubkey).with_references(vec!["ref".to_string()]));
+
+        assert!(cache.references_taken("second", &["other".to_string(), "ref".to_string()]));
+        assert!(!cache.references_taken("first", &["ref".to_string()]));
+        assert!(!cache.references_taken("second", &["other".to_string()]));
+
+        // A payment coming again under its id keeps its reference
+        cache.insert(Payment::new("first".to_string(), 1, 100, None, &pubkey).with_references(vec!["ref".to_string()]));
+        assert!(cache.references_taken("second", &["ref".to_string()]));
+
+        // The reference moves on to a payment taking it after the first one is gone, and stays with it
+        cache.remove("first");
+        cache.insert(Payment::new("second".to_string(), 2, 100, None, &pubkey).with_references(vec!["ref".to_string()]));
+        cache.remove("first");
+
+        let account_keys = ["ref".to_string()];
+        assert_eq!(cache.select(&pubkey, &None, None, &account_keys, 100, Matching::Fifo).as_deref(), Some("second"));
+    }
+}
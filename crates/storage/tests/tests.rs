@@ -0,0 +1,5 @@
+mod payments {
+    mod actor;
+    mod expiry;
+    mod matching;
+}
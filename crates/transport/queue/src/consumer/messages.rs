@@ -7,6 +7,26 @@ pub struct TransferMsg {
     pub id: String,
     pub address: String,
     pub amount: u64,
+
+    /// Token mint address, `None` for the native currency
+    #[serde(default)]
+    pub mint: Option<String>,
+
+    /// Only transfers whose transaction carries this memo are credited
+    #[serde(default)]
+    pub memo: Option<String>,
+
+    /// Solana Pay reference keys, only transfers whose transaction holds one of them are credited
+    #[serde(default)]
+    pub references: Vec<String>,
+
+    /// Unix timestamp the payment expires at, it takes precedence over `ttl`
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+
+    /// Seconds the payment stays open after it is received
+    #[serde(default)]
+    pub ttl: Option<u64>,
 }
 
 pub struct ConsumerMsg<T> {
@@ -3,23 +3,75 @@ use serde::{Deserialize, Serialize};
 use crate::consumer::messages::DeliveryTag;
 
 #[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
 pub struct ResultMsg {
     pub id: String,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub signatures: Option<Vec<String>>,
+    pub signatures: Option<Vec<SignatureMsg>>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    error: Option<()>
+    pub error: Option<ErrorMsg>,
+
+    /// Unix timestamp the payment was completed at
+    pub completed_at: u64,
+
+    /// Amount credited before the payment expired
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub received: Option<u64>,
 }
 
 impl ResultMsg {
     pub fn new(
         id: String,
-        signatures: Option<Vec<String>>,
-        error: Option<()>,
+        signatures: Option<Vec<SignatureMsg>>,
+        error: Option<ErrorMsg>,
+        completed_at: u64,
     ) -> Self {
-        Self { id, signatures, error }
+        Self { id, signatures, error, completed_at, received: None }
+    }
+
+    pub fn with_received(mut self, received: Option<u64>) -> Self {
+        self.received = received;
+        self
+    }
+}
+
+/// Why the payment was not accepted
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorMsg {
+    /// Another open payment on the address could be paid by the same amount
+    ConflictingAmount,
+
+    /// The payment was not paid in full before it expired
+    Expired,
+
+    /// One of the references belongs to another open payment
+    DuplicateReference,
+}
+
+/// A transfer credited to the payment
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SignatureMsg {
+    pub signature: String,
+
+    /// Account which funded the transfer, `None` if the block did not tell
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+
+    pub amount: u64,
+    pub slot: u64,
+
+    /// Unix timestamp of the block, `None` if the node did not know it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_time: Option<i64>,
+}
+
+impl SignatureMsg {
+    pub fn new(signature: String, from: Option<String>, amount: u64, slot: u64, block_time: Option<i64>) -> Self {
+        Self { signature, from, amount, slot, block_time }
     }
 }
 
@@ -20,7 +20,7 @@ pub async fn confirm_transfer(
         let signature = signature.to_string();
         assert!(result.signatures.unwrap()
             .into_iter()
-            .any(|s| s == signature)
+            .any(|s| s.signature == signature)
         );
         assert_eq!(id.to_string(), result.id);
 
@@ -58,7 +58,7 @@ async fn main() -> anyhow::Result<()> {
     let rabbitmq_connection = rabbitmqlib::connect(&rabbitmq_config).await?;
     let channel = rabbitmq_connection.open_channel(None).await?;
 
-    let rpc_client = RpcClient::new(network_config.rpc.http_endpoint_url);
+    let rpc_client = RpcClient::new(network_config.rpc.endpoints[0].http_endpoint_url.clone());
 
     let height_connection = leveldblib::connect(&network_config.db.height_path)?;
 
@@ -1,11 +1,12 @@
-use anyhow::Result;
+use std::time::Duration;
+
+use anyhow::{bail, Result};
 use app::application::transfer::TransferActor;
 use config::args;
-use fastwebsocketslib;
 use log::error;
 use queue::{consumer::ConsumerActor, producer::ProducerActor};
-use storage::{height::HeightActor, payments::PaymentsActor};
-use tokio::{signal, sync::mpsc::unbounded_channel};
+use storage::{height::HeightActor, payments::{models::Matching, PaymentsActor}};
+use tokio::{signal, sync::{mpsc::unbounded_channel, watch}};
 use hyperlib;
 use tokio_util::sync::CancellationToken;
 
@@ -19,12 +20,15 @@ async fn main() -> Result<()> {
 
     let rabbitmq_config = config::rabbitmq::load()?;
 
+    let metrics_config = config::metrics::load()?;
+    metricslib::install(&metrics_config)?;
+
     let token = CancellationToken::new();
 
     #[cfg(feature = "solana")]
     {
         use solana::{
-            data::{block::BlockService, slot::SlotActor},
+            data::{address::{pending::{PendingAddresses, PendingAddressesActor}, poll::AddressPollActor, watch::AddressWatch, AddressRepo}, block::{BlockService, TransferParsing}, endpoint::{Endpoint, EndpointPool}, req::{Commitment, TransactionDetails}, slot::SlotActor, source::BlockSource},
             service::{transfers::TransfersServiceActor, parser::Parser},
         };
 
@@ -33,6 +37,8 @@ async fn main() -> Result<()> {
         let queues_config = network_config.queues;
         let rpc_config = network_config.rpc;
         let db_config = network_config.db;
+        let block_config = network_config.block;
+        let payments_config = network_config.payments;
 
         let transfer_queue_name = queues_config.input_queue_name;
         let result_queue_name = queues_config.output_queue_name;
@@ -48,35 +54,131 @@ async fn main() -> Result<()> {
         let height_connection = leveldblib::connect(&db_config.height_path)?;
         let (height_actor, height_tx) = HeightActor::new(height_connection);
         let height = height_actor.get_height()?;
+        let retries = height_actor.get_retries()?;
         tokio::spawn(height_actor.start(token.clone()));
 
-        let fc = fastwebsocketslib::connect(&rpc_config.ws_endpoint_url).await?;
-        let (slot_actor, slot_tx) = SlotActor::new(fc, height);
-        tokio::spawn(slot_actor.start(token.clone()));
+        let commitment = match block_config.commitment {
+            config::network::Commitment::Confirmed => Commitment::Confirmed,
+            config::network::Commitment::Finalized => Commitment::Finalized,
+        };
+
+        // Buffered address lookups cannot be checked against finality, so they only run at `finalized`
+        if let (config::network::Detection::Accounts | config::network::Detection::Signatures, Commitment::Confirmed) = (block_config.detection, commitment) {
+            bail!("err 'address detection requires finalized commitment' in main()");
+        }
+
+        // Memos are read from instructions, which `accounts` details leave out of blocks
+        let reads_instructions = matches!(block_config.transaction_details, config::network::TransactionDetails::Full)
+            || matches!(block_config.transfer_parsing, config::network::TransferParsing::Instructions);
+
+        if payments_config.memos && matches!(block_config.detection, config::network::Detection::Blocks) && !reads_instructions {
+            bail!("err 'memo matching requires full transaction details or instruction parsing' in main()");
+        }
+
+        let endpoints = rpc_config.endpoints.into_iter()
+            .map(|e| Endpoint {
+                http_url: e.http_endpoint_url,
+                ws_url: e.ws_endpoint_url,
+                priority: e.priority,
+                requests_per_second: e.requests_per_second,
+                burst: e.burst,
+            })
+            .collect();
+        let endpoint_pool = EndpointPool::new(endpoints)?;
+
+        let (slot_actor, slot_tx) = SlotActor::new(endpoint_pool.clone());
 
         let payments_connection = leveldblib::connect(&db_config.payments_path)?;
         let (payments_tx, payments_rx) = unbounded_channel();
         let (transfers_tx, transfers_rx) = unbounded_channel();
         let (processed_tx, processed_rx) = unbounded_channel();
-        let payments_actor = PaymentsActor::new(payments_rx, transfers_rx, processed_tx, payments_connection);
+        let (pending_tx, pending_rx) = watch::channel(0);
+        let payments_actor = PaymentsActor::new(payments_rx, transfers_rx, processed_tx, pending_tx, payments_connection)
+            .with_matching(match payments_config.matching {
+                config::network::Matching::Fifo => Matching::Fifo,
+                config::network::Matching::Amount => Matching::Amount,
+                config::network::Matching::Unique => Matching::Unique { tolerance: payments_config.amount_tolerance },
+            })
+            .with_sweep_interval(Duration::from_millis(payments_config.sweep_interval_ms));
+
+        let block_service = BlockService::new(
+            hyperlib::connect(),
+            endpoint_pool,
+            block_config.skip_failed_transactions,
+        )
+        .with_transaction_details(match block_config.transaction_details {
+            config::network::TransactionDetails::Full => TransactionDetails::Full,
+            config::network::TransactionDetails::Accounts => TransactionDetails::Accounts,
+        })
+        .with_transfer_parsing(match block_config.transfer_parsing {
+            config::network::TransferParsing::Balances => TransferParsing::Balances,
+            config::network::TransferParsing::Instructions => TransferParsing::Instructions,
+        });
+
+        let (slot_actor, payments_actor, block_source) = match block_config.detection {
+            config::network::Detection::Blocks => {
+                let pending = PendingAddresses::default();
+                let (address_tx, address_rx) = unbounded_channel();
+
+                let pending_actor = PendingAddressesActor::new(address_rx, pending.clone());
+                tokio::spawn(pending_actor.start(token.clone()));
+
+                (
+                    slot_actor,
+                    payments_actor.with_address_tx(address_tx),
+                    BlockSource::Blocks(block_service.with_pending_addresses(pending)),
+                )
+            },
+
+            config::network::Detection::Accounts => {
+                let watch = AddressWatch::default();
+                let (address_tx, address_rx) = unbounded_channel();
+
+                (
+                    slot_actor.with_accounts(address_rx, watch.clone(), commitment),
+                    payments_actor.with_address_tx(address_tx),
+                    BlockSource::Addresses(AddressRepo::new(block_service, watch)),
+                )
+            },
+
+            config::network::Detection::Signatures => {
+                let watch = AddressWatch::default();
+                let (address_tx, address_rx) = unbounded_channel();
+
+                let poll_actor = AddressPollActor::new(address_rx, watch.clone(), Duration::from_millis(block_config.poll_interval_ms));
+                tokio::spawn(poll_actor.start(token.clone()));
+
+                (
+                    slot_actor,
+                    payments_actor.with_address_tx(address_tx),
+                    BlockSource::Addresses(AddressRepo::new(block_service, watch)),
+                )
+            },
+        };
+
+        tokio::spawn(slot_actor.start(token.clone()));
         tokio::spawn(payments_actor.start(token.clone()));
 
-        let (state_tx, state_rx) = unbounded_channel();
-        let block_service = BlockService::new(hyperlib::connect(), rpc_config.http_endpoint_url);
         let transfer_service_actor = TransfersServiceActor::new(
-            state_rx, slot_tx,
+            pending_rx, slot_tx,
             transfers_tx, height_tx,
-            block_service,
-        );
+            block_source, height,
+        )
+        .with_retries(retries)
+        .with_concurrency(block_config.concurrency)
+        .with_batch_size(block_config.batch_size)
+        .with_commitment(commitment)
+        .with_confirmation_lag(block_config.confirmation_lag);
         tokio::spawn(transfer_service_actor.start(token.clone()));
 
         let parser = Parser;
         let transfer_actor = TransferActor::new(
             messages_rx, payments_tx,
-            state_tx, processed_rx,
+            processed_rx,
             producer_tx,
             parser,
-        );
+        )
+        .with_memos(payments_config.memos);
         transfer_actor.start(token.clone()).await;
     }
 
@@ -5,7 +5,7 @@ mod tests {
     use queue::{consumer::ConsumerActor, producer::{messages::ResultMsg, ProducerActor}};
     use solana::{data::{block::mock::BlockServiceMock, slot::slot_mock::SlotActorMock}, service::{transfers::TransfersServiceActor, parser::Parser}};
     use storage::{height::HeightActor, payments::PaymentsActor};
-    use tokio::sync::mpsc::unbounded_channel;
+    use tokio::sync::{mpsc::unbounded_channel, watch};
     use tokio_util::sync::CancellationToken;
 
     #[tokio::test]
@@ -49,22 +49,22 @@ mod tests {
         let (payments_tx, payments_rx) = unbounded_channel();
         let (transfers_tx, transfers_rx) = unbounded_channel();
         let (processed_tx, processed_rx) = unbounded_channel();
-        let payments_actor = PaymentsActor::new(payments_rx, transfers_rx, processed_tx, payments_connection);
+        let (pending_tx, pending_rx) = watch::channel(0);
+        let payments_actor = PaymentsActor::new(payments_rx, transfers_rx, processed_tx, pending_tx, payments_connection);
         tokio::spawn(payments_actor.start(token.clone()));
 
-        let (state_tx, state_rx) = unbounded_channel();
         let block_service = BlockServiceMock::new(ADDRESS.into(), AMOUNT, SIGNATURE.into());
         let transfer_service_actor = TransfersServiceActor::new(
-            state_rx, slot_tx,
+            pending_rx, slot_tx,
             transfers_tx, height_tx,
-            block_service,
+            block_service, None,
         );
         tokio::spawn(transfer_service_actor.start(token.clone()));
 
         let parser = Parser;
         let transfer_actor = TransferActor::new(
             messages_rx, payments_tx,
-            state_tx, processed_rx,
+            processed_rx,
             producer_tx,
             parser,
         );
@@ -94,7 +94,7 @@ mod tests {
                 let signature = SIGNATURE.to_string();
                 assert!(result.signatures.unwrap()
                     .into_iter()
-                    .any(|s| s == signature)
+                    .any(|s| s.signature == signature)
                 );
                 assert_eq!(ID.to_string(), result.id);
 